@@ -0,0 +1,33 @@
+// Compiles the runtime into a static library linked into the compiler, so that `cody run` can
+// bind the programs it runs in memory to it.
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is set by cargo."));
+    let object = out_dir.join("cody_runtime.o");
+    let library = out_dir.join("libcody_runtime.a");
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+
+    let status = Command::new(&compiler)
+        .args(["-c", "-O2", "-fPIC", "runtime/cody_runtime.c", "-o"])
+        .arg(&object)
+        .status()
+        .unwrap_or_else(|error| panic!("Failed to run the C compiler {}: {}", compiler, error));
+    assert!(status.success(), "Failed to compile the runtime.");
+
+    let status = Command::new("ar")
+        .arg("crs")
+        .arg(&library)
+        .arg(&object)
+        .status()
+        .unwrap_or_else(|error| panic!("Failed to run ar: {}", error));
+    assert!(status.success(), "Failed to archive the runtime.");
+
+    println!("cargo:rustc-link-search=native={}", out_dir.display());
+    println!("cargo:rustc-link-lib=static=cody_runtime");
+    println!("cargo:rerun-if-changed=runtime/cody_runtime.c");
+    println!("cargo:rerun-if-env-changed=CC");
+}
@@ -0,0 +1,696 @@
+/*
+ * The cody runtime, linked into every cody program.
+ *
+ * Provides the heap that pairs and closures are allocated on, managed by a mark-sweep
+ * garbage collector. Roots are found on the root stack, where every function compiled by
+ * cody keeps a frame of slots for its variables, and in the global variables registered
+ * with cody_gc_register_root.
+ *
+ * Also provides escaping continuations, captured with setjmp and resumed with longjmp, the
+ * operations on strings, and the errors compiled code reports when given a value of the wrong type.
+ * The REPL runs each of its forms through cody_run_form, which returns from errors rather than
+ * ending the program, and shows their values with cody_write.
+ */
+
+#include <setjmp.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+/* value representation, see src/compiler/value.rs */
+#define TAG_MASK 0x7u
+#define INTEGER_TAG 0x0u
+#define PAIR_TAG 0x1u
+#define CLOSURE_TAG 0x2u
+#define SYMBOL_TAG 0x3u
+#define FLOAT_TAG 0x4u
+#define STRING_TAG 0x5u
+#define RECORD_TAG 0x6u
+#define IMMEDIATE_TAG 0x7u
+
+#define IMMEDIATE_MASK 0xffu
+#define BOOLEAN_KIND 0xfu
+#define CHARACTER_KIND 0x17u
+
+#define NIL_VALUE 0x7u
+#define FALSE_VALUE 0xfu
+#define TRUE_VALUE 0x10fu
+#define BOOLEAN_VALUE(b) ((b) ? TRUE_VALUE : FALSE_VALUE)
+#define INTEGER_VALUE(i) ((uint64_t)(i) << 3)
+#define INTEGER_OF(value) ((int64_t)(value) >> 3)
+#define CHARACTER_VALUE(c) ((uint64_t)(c) << 8 | CHARACTER_KIND)
+
+/* ---------------------------------------------------------------------------------------- */
+/* errors                                                                                    */
+
+/* writes what a value is, reading no more of the record it points to than its first word */
+static void describe(FILE *out, uint64_t value) {
+    switch (value & TAG_MASK) {
+    case INTEGER_TAG:
+        fprintf(out, "the integer %lld", (long long)INTEGER_OF(value));
+        return;
+    case PAIR_TAG:
+        fprintf(out, "a pair");
+        return;
+    case CLOSURE_TAG:
+        fprintf(out, "a function");
+        return;
+    case SYMBOL_TAG:
+        /* the names of symbols are constants of the program */
+        fprintf(out, "the symbol %s", (const char *)(uintptr_t)(value & ~(uint64_t)TAG_MASK));
+        return;
+    case FLOAT_TAG:
+        fprintf(out, "a float");
+        return;
+    case STRING_TAG:
+        fprintf(out, "a string");
+        return;
+    case RECORD_TAG: {
+        /* the number of a variant, or the negated type code of a bit-precise integer */
+        const uint64_t *record = (const uint64_t *)(uintptr_t)(value & ~(uint64_t)TAG_MASK);
+        int64_t first = INTEGER_OF(record[0]);
+        if (first >= 0) {
+            fprintf(out, "a value of an enum");
+        } else if (-first & 1) {
+            fprintf(out, "the i%lld %lld", (long long)(-first >> 1), (long long)record[1]);
+        } else {
+            fprintf(out, "the u%lld %llu", (long long)(-first >> 1), (unsigned long long)record[1]);
+        }
+        return;
+    }
+    }
+    if (value == NIL_VALUE) {
+        fprintf(out, "()");
+    } else if ((value & IMMEDIATE_MASK) == BOOLEAN_KIND) {
+        fprintf(out, value == TRUE_VALUE ? "true" : "false");
+    } else if ((value & IMMEDIATE_MASK) == CHARACTER_KIND && (value >> 8) > ' ' && (value >> 8) < 0x7f) {
+        fprintf(out, "the character #\\%c", (char)(value >> 8));
+    } else if ((value & IMMEDIATE_MASK) == CHARACTER_KIND) {
+        fprintf(out, "the character with code %llu", (unsigned long long)(value >> 8));
+    } else {
+        fprintf(out, "the unknown value %#llx", (unsigned long long)value);
+    }
+}
+
+/* where errors return to while a form of the REPL runs, or NULL if they end the program */
+static jmp_buf *error_return;
+
+/* ends the program after an error, or the form of the REPL that is running */
+__attribute__((noreturn)) static void fail(void) {
+    if (error_return != NULL) {
+        longjmp(*error_return, 1);
+    }
+    exit(1);
+}
+
+/* aborts the program, as an operation was given a value it cannot work on */
+__attribute__((noreturn)) void cody_type_error(const char *operation, const char *expected, uint64_t value) {
+    fflush(stdout);
+    fprintf(stderr, "cody: type error: %s expects %s, but was given ", operation, expected);
+    describe(stderr, value);
+    fputc('\n', stderr);
+    fail();
+}
+
+/* aborts the program with a message */
+__attribute__((noreturn)) void cody_error(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "cody: %s\n", message);
+    fail();
+}
+
+/* ---------------------------------------------------------------------------------------- */
+/* root stack                                                                                */
+
+/* the size of the root stack in words */
+#define ROOT_STACK_SIZE ((size_t)1 << 22)
+
+static uint64_t *root_stack;
+
+/* the end of the frames in use, pushed and popped by compiled code */
+uint64_t *cody_root_stack_top;
+
+__attribute__((constructor)) static void init_root_stack(void) {
+    size_t bytes = ROOT_STACK_SIZE * sizeof(uint64_t);
+    size_t page = (size_t)sysconf(_SC_PAGESIZE);
+    char *memory = mmap(NULL, bytes + page, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE, -1, 0);
+    if (memory == MAP_FAILED) {
+        fprintf(stderr, "cody: failed to allocate the root stack\n");
+        exit(1);
+    }
+    /* a guard page turns overflowing the root stack into a fault, like overflowing the machine stack */
+    mprotect(memory + bytes, page, PROT_NONE);
+    root_stack = (uint64_t *)memory;
+    cody_root_stack_top = root_stack;
+}
+
+/* holds a value on the root stack, keeping it alive while the runtime allocates */
+static void push_root(uint64_t value) {
+    *cody_root_stack_top++ = value;
+}
+
+static void pop_roots(size_t count) {
+    cody_root_stack_top -= count;
+}
+
+/* ---------------------------------------------------------------------------------------- */
+/* heap objects                                                                              */
+
+/* every object is preceded by a header linking it into the list of all objects */
+struct Header {
+    struct Header *next;
+    uint32_t size;      /* size of the payload in words */
+    uint8_t marked;
+    uint8_t scanned;    /* whether the payload holds values the collector has to trace */
+};
+
+#define PAYLOAD(header) ((uint64_t *)((struct Header *)(header) + 1))
+#define HEADER(payload) ((struct Header *)(payload) - 1)
+
+static struct Header *objects;
+static size_t allocated_bytes;
+static size_t live_bytes;
+static size_t collection_threshold = 1 << 20;
+
+/* the addresses of every object's payload, used to tell heap pointers apart from other words */
+static uint64_t **object_table;
+static size_t object_table_capacity;
+static size_t object_count;
+
+/* the global variables holding values */
+static uint64_t **global_roots;
+static size_t global_root_count;
+static size_t global_root_capacity;
+
+/* objects that have been marked but whose fields have not been traced yet */
+static uint64_t **mark_stack;
+static size_t mark_stack_count;
+static size_t mark_stack_capacity;
+
+static void *checked_malloc(size_t bytes) {
+    void *memory = malloc(bytes);
+    if (memory == NULL) {
+        fprintf(stderr, "cody: out of memory\n");
+        exit(1);
+    }
+    return memory;
+}
+
+static void *checked_realloc(void *memory, size_t bytes) {
+    memory = realloc(memory, bytes);
+    if (memory == NULL) {
+        fprintf(stderr, "cody: out of memory\n");
+        exit(1);
+    }
+    return memory;
+}
+
+static size_t hash_address(uint64_t *payload, size_t capacity) {
+    return (size_t)(((uintptr_t)payload >> 4) * 11400714819323198485ull) & (capacity - 1);
+}
+
+static void object_table_insert(uint64_t *payload) {
+    size_t i = hash_address(payload, object_table_capacity);
+    while (object_table[i] != NULL) {
+        i = (i + 1) & (object_table_capacity - 1);
+    }
+    object_table[i] = payload;
+}
+
+/* rebuilds the object table from the list of objects, growing it to keep it at most half full */
+static void object_table_rebuild(void) {
+    size_t capacity = object_table_capacity == 0 ? 1024 : object_table_capacity;
+    while (capacity < object_count * 2) {
+        capacity *= 2;
+    }
+    if (capacity != object_table_capacity) {
+        free(object_table);
+        object_table = checked_malloc(capacity * sizeof(uint64_t *));
+        object_table_capacity = capacity;
+    }
+    memset(object_table, 0, object_table_capacity * sizeof(uint64_t *));
+    for (struct Header *header = objects; header != NULL; header = header->next) {
+        object_table_insert(PAYLOAD(header));
+    }
+}
+
+static int is_object(uint64_t *payload) {
+    if (object_table_capacity == 0) {
+        return 0;
+    }
+    size_t i = hash_address(payload, object_table_capacity);
+    while (object_table[i] != NULL) {
+        if (object_table[i] == payload) {
+            return 1;
+        }
+        i = (i + 1) & (object_table_capacity - 1);
+    }
+    return 0;
+}
+
+/* ---------------------------------------------------------------------------------------- */
+/* collection                                                                                */
+
+/* marks the object a value points to, if it points to one */
+static void mark_value(uint64_t value) {
+    uint64_t tag = value & TAG_MASK;
+    if (tag != PAIR_TAG && tag != CLOSURE_TAG && tag != FLOAT_TAG && tag != STRING_TAG && tag != RECORD_TAG) {
+        return;
+    }
+    /* closures without captured variables, float literals, string literals, variants
+       without fields and bit-precise integer literals are constants outside of the heap */
+    uint64_t *payload = (uint64_t *)(uintptr_t)(value & ~(uint64_t)TAG_MASK);
+    if (!is_object(payload)) {
+        return;
+    }
+    struct Header *header = HEADER(payload);
+    if (header->marked) {
+        return;
+    }
+    header->marked = 1;
+    if (!header->scanned) {
+        return;
+    }
+    if (mark_stack_count == mark_stack_capacity) {
+        mark_stack_capacity = mark_stack_capacity == 0 ? 256 : mark_stack_capacity * 2;
+        mark_stack = checked_realloc(mark_stack, mark_stack_capacity * sizeof(uint64_t *));
+    }
+    mark_stack[mark_stack_count++] = payload;
+}
+
+static void mark_roots(void) {
+    for (uint64_t *slot = root_stack; slot < cody_root_stack_top; slot++) {
+        mark_value(*slot);
+    }
+    for (size_t i = 0; i < global_root_count; i++) {
+        mark_value(*global_roots[i]);
+    }
+}
+
+/* traces the fields of marked objects, without recursing so that long lists can be traced */
+static void trace(void) {
+    while (mark_stack_count > 0) {
+        uint64_t *payload = mark_stack[--mark_stack_count];
+        uint32_t size = HEADER(payload)->size;
+        for (uint32_t i = 0; i < size; i++) {
+            mark_value(payload[i]);
+        }
+    }
+}
+
+static void sweep(void) {
+    struct Header **link = &objects;
+    live_bytes = 0;
+    object_count = 0;
+    while (*link != NULL) {
+        struct Header *header = *link;
+        if (header->marked) {
+            header->marked = 0;
+            live_bytes += sizeof(struct Header) + header->size * sizeof(uint64_t);
+            object_count++;
+            link = &header->next;
+        } else {
+            *link = header->next;
+            free(header);
+        }
+    }
+    object_table_rebuild();
+}
+
+void cody_gc_collect(void) {
+    mark_roots();
+    trace();
+    sweep();
+
+    allocated_bytes = 0;
+    /* let the heap grow in proportion to the data that survives */
+    collection_threshold = live_bytes < (1 << 20) ? (1 << 20) : live_bytes * 2;
+}
+
+/* ---------------------------------------------------------------------------------------- */
+/* interface used by compiled code                                                           */
+
+static uint64_t *allocate(uint64_t size, int scanned) {
+    size_t bytes = sizeof(struct Header) + size * sizeof(uint64_t);
+    if (allocated_bytes + bytes > collection_threshold) {
+        cody_gc_collect();
+    }
+    allocated_bytes += bytes;
+
+    struct Header *header = checked_malloc(bytes);
+    header->next = objects;
+    header->size = (uint32_t)size;
+    header->marked = 0;
+    header->scanned = (uint8_t)scanned;
+    objects = header;
+
+    object_count++;
+    if (object_count * 2 > object_table_capacity) {
+        object_table_rebuild();
+    } else {
+        object_table_insert(PAYLOAD(header));
+    }
+    memset(PAYLOAD(header), 0, size * sizeof(uint64_t));
+    return PAYLOAD(header);
+}
+
+/* allocates a record of `size` values on the heap, which may trigger a collection */
+void *cody_gc_alloc(uint64_t size) {
+    return allocate(size, 1);
+}
+
+/* allocates `bytes` of data holding no values on the heap, which may trigger a collection */
+void *cody_gc_alloc_raw(uint64_t bytes) {
+    return allocate((bytes + sizeof(uint64_t) - 1) / sizeof(uint64_t), 0);
+}
+
+/* registers a global variable holding a value as a root */
+void cody_gc_register_root(uint64_t *slot) {
+    if (global_root_count == global_root_capacity) {
+        global_root_capacity = global_root_capacity == 0 ? 64 : global_root_capacity * 2;
+        global_roots = checked_realloc(global_roots, global_root_capacity * sizeof(uint64_t *));
+    }
+    global_roots[global_root_count++] = slot;
+}
+
+/* ---------------------------------------------------------------------------------------- */
+/* continuations                                                                             */
+
+/* an escaping continuation, a heap object holding no values that is called like a closure */
+struct Continuation {
+    uint64_t code;              /* the address of cody.continue, which resumes the continuation */
+    uint64_t arity;             /* the number of arguments it is called with, 1, as an integer */
+    struct Continuation *next;  /* the continuation captured before this one */
+    uint64_t *root_top;         /* the end of the root stack when the continuation was captured */
+    uint64_t value;             /* the value the continuation is resumed with */
+    uint64_t active;            /* whether the call the continuation returns from is still running */
+    jmp_buf buffer;
+};
+
+#define CONTINUATION(value) ((struct Continuation *)(uintptr_t)((value) & ~(uint64_t)TAG_MASK))
+
+/* the innermost continuation that can still be resumed */
+static struct Continuation *active_continuations;
+
+/* captures the current continuation, before its buffer is filled in by setjmp */
+uint64_t cody_continuation_new(uint64_t code) {
+    struct Continuation *continuation = cody_gc_alloc_raw(sizeof(struct Continuation));
+    continuation->code = code;
+    continuation->arity = INTEGER_VALUE(1);
+    continuation->next = active_continuations;
+    continuation->root_top = cody_root_stack_top;
+    continuation->active = 1;
+    active_continuations = continuation;
+    return (uint64_t)(uintptr_t)continuation | CLOSURE_TAG;
+}
+
+void *cody_continuation_buffer(uint64_t continuation) {
+    return CONTINUATION(continuation)->buffer;
+}
+
+/* ends the extent of the innermost continuation, once the call it returns from is done */
+void cody_continuation_end(uint64_t continuation) {
+    struct Continuation *innermost = CONTINUATION(continuation);
+    innermost->active = 0;
+    active_continuations = innermost->next;
+}
+
+uint64_t cody_continuation_value(uint64_t continuation) {
+    return CONTINUATION(continuation)->value;
+}
+
+/* resumes a continuation with a value */
+void cody_continue(uint64_t continuation, uint64_t value) {
+    struct Continuation *target = CONTINUATION(continuation);
+    if (!target->active) {
+        fprintf(stderr, "cody: continuation resumed after the call capturing it returned\n");
+        fail();
+    }
+    /* the continuations captured within the target's extent end with it */
+    while (active_continuations != target) {
+        active_continuations->active = 0;
+        active_continuations = active_continuations->next;
+    }
+    cody_continuation_end(continuation);
+
+    target->value = value;
+    cody_root_stack_top = target->root_top;
+    longjmp(target->buffer, 1);
+}
+
+/* ---------------------------------------------------------------------------------------- */
+/* strings                                                                                   */
+
+/* a string, a heap object holding no values: its length in bytes, then its bytes and a NUL */
+struct String {
+    uint64_t length;
+    char bytes[];
+};
+
+/* the string a value points to, which is a type error for any other value */
+static struct String *string_of(const char *operation, uint64_t value) {
+    if ((value & TAG_MASK) != STRING_TAG) {
+        cody_type_error(operation, "a string", value);
+    }
+    return (struct String *)(uintptr_t)(value & ~(uint64_t)TAG_MASK);
+}
+
+static struct String *new_string(uint64_t length) {
+    struct String *string = cody_gc_alloc_raw(sizeof(struct String) + length + 1);
+    string->length = length;
+    string->bytes[length] = '\0';
+    return string;
+}
+
+static uint64_t string_value(struct String *string) {
+    return (uint64_t)(uintptr_t)string | STRING_TAG;
+}
+
+uint64_t cody_string_length(uint64_t string) {
+    return INTEGER_VALUE(string_of("string-length", string)->length);
+}
+
+/* the character of the byte at an index */
+uint64_t cody_string_ref(uint64_t string, uint64_t index) {
+    struct String *s = string_of("string-ref", string);
+    if ((index & TAG_MASK) != INTEGER_TAG) {
+        cody_type_error("string-ref", "an integer", index);
+    }
+    int64_t i = INTEGER_OF(index);
+    if (i < 0 || (uint64_t)i >= s->length) {
+        fflush(stdout);
+        fprintf(stderr, "cody: string index %lld out of bounds for a string of length %llu\n",
+                (long long)i, (unsigned long long)s->length);
+        fail();
+    }
+    return CHARACTER_VALUE((unsigned char)s->bytes[i]);
+}
+
+uint64_t cody_string_append(uint64_t first, uint64_t second) {
+    uint64_t length = string_of("string-append", first)->length + string_of("string-append", second)->length;
+    push_root(first);
+    push_root(second);
+    struct String *string = new_string(length);
+    pop_roots(2);
+    /* the strings are where they were, as the collector does not move objects */
+    struct String *a = string_of("string-append", first), *b = string_of("string-append", second);
+    memcpy(string->bytes, a->bytes, a->length);
+    memcpy(string->bytes + a->length, b->bytes, b->length);
+    return string_value(string);
+}
+
+uint64_t cody_string_equal(uint64_t first, uint64_t second) {
+    struct String *a = string_of("string-equal?", first), *b = string_of("string-equal?", second);
+    return BOOLEAN_VALUE(a->length == b->length && memcmp(a->bytes, b->bytes, a->length) == 0);
+}
+
+/* whether a string comes before another, comparing their bytes in order */
+uint64_t cody_string_less(uint64_t first, uint64_t second) {
+    struct String *a = string_of("string-less?", first), *b = string_of("string-less?", second);
+    uint64_t length = a->length < b->length ? a->length : b->length;
+    int order = memcmp(a->bytes, b->bytes, length);
+    return BOOLEAN_VALUE(order < 0 || (order == 0 && a->length < b->length));
+}
+
+/* the decimal digits of an integer */
+uint64_t cody_integer_string(uint64_t integer) {
+    if ((integer & TAG_MASK) != INTEGER_TAG) {
+        cody_type_error("integer-string", "an integer", integer);
+    }
+    char digits[32];
+    int length = snprintf(digits, sizeof digits, "%lld", (long long)INTEGER_OF(integer));
+    struct String *string = new_string((uint64_t)length);
+    memcpy(string->bytes, digits, (size_t)length);
+    return string_value(string);
+}
+
+/* the integer a string of decimal digits stands for, aborting if it is not one */
+uint64_t cody_string_integer(uint64_t string) {
+    struct String *s = string_of("string-integer", string);
+    char *end = s->bytes;
+    long long integer = 0;
+    if (s->length != 0 && strlen(s->bytes) == s->length) {
+        integer = strtoll(s->bytes, &end, 10);
+    }
+    if (end == s->bytes || *end != '\0' || integer > INTEGER_OF(INT64_MAX) || integer < INTEGER_OF(INT64_MIN)) {
+        cody_type_error("string-integer", "a string of decimal digits", string);
+    }
+    return INTEGER_VALUE(integer);
+}
+
+uint64_t cody_print(uint64_t string) {
+    struct String *s = string_of("print", string);
+    fwrite(s->bytes, 1, s->length, stdout);
+    return NIL_VALUE;
+}
+
+/* ---------------------------------------------------------------------------------------- */
+/* writing values                                                                            */
+
+static void write_float(double f) {
+    /* the fewest digits that read back as the same float */
+    char digits[32];
+    for (int precision = 1; precision <= 17; precision++) {
+        snprintf(digits, sizeof digits, "%.*g", precision, f);
+        if (strtod(digits, NULL) == f) {
+            break;
+        }
+    }
+    fputs(digits, stdout);
+    if (strpbrk(digits, ".eni") == NULL) {
+        fputs(".0", stdout);
+    }
+}
+
+static void write_string(const struct String *string) {
+    putchar('"');
+    for (uint64_t i = 0; i < string->length; i++) {
+        char c = string->bytes[i];
+        switch (c) {
+        case '"': fputs("\\\"", stdout); break;
+        case '\\': fputs("\\\\", stdout); break;
+        case '\n': fputs("\\n", stdout); break;
+        case '\t': fputs("\\t", stdout); break;
+        case '\r': fputs("\\r", stdout); break;
+        case '\0': fputs("\\0", stdout); break;
+        default: putchar(c);
+        }
+    }
+    putchar('"');
+}
+
+/* a record is a value of an enum, whose fields are written after the number of its variant,
+   or a bit-precise integer, written as its literal */
+static void write_record(const uint64_t *record);
+
+/* writes a value the way it is written in a program, where it can be */
+static void write_value(uint64_t value) {
+    const uint64_t *payload = (const uint64_t *)(uintptr_t)(value & ~(uint64_t)TAG_MASK);
+    switch (value & TAG_MASK) {
+    case INTEGER_TAG:
+        printf("%lld", (long long)INTEGER_OF(value));
+        return;
+    case PAIR_TAG:
+        putchar('(');
+        write_value(payload[0]);
+        /* lists are written without the pairs they are made of */
+        for (value = payload[1]; (value & TAG_MASK) == PAIR_TAG; value = payload[1]) {
+            payload = (const uint64_t *)(uintptr_t)(value & ~(uint64_t)TAG_MASK);
+            putchar(' ');
+            write_value(payload[0]);
+        }
+        if (value != NIL_VALUE) {
+            fputs(" . ", stdout);
+            write_value(value);
+        }
+        putchar(')');
+        return;
+    case CLOSURE_TAG:
+        fputs("#<function>", stdout);
+        return;
+    case SYMBOL_TAG:
+        fputs((const char *)payload, stdout);
+        return;
+    case FLOAT_TAG:
+        write_float(*(const double *)payload);
+        return;
+    case STRING_TAG:
+        write_string((const struct String *)payload);
+        return;
+    case RECORD_TAG:
+        write_record(payload);
+        return;
+    }
+    if (value == NIL_VALUE) {
+        fputs("()", stdout);
+    } else if ((value & IMMEDIATE_MASK) == BOOLEAN_KIND) {
+        fputs(value == TRUE_VALUE ? "true" : "false", stdout);
+    } else if (value == CHARACTER_VALUE(' ')) {
+        fputs("#\\space", stdout);
+    } else if (value == CHARACTER_VALUE('\n')) {
+        fputs("#\\newline", stdout);
+    } else if (value == CHARACTER_VALUE('\t')) {
+        fputs("#\\tab", stdout);
+    } else if ((value & IMMEDIATE_MASK) == CHARACTER_KIND && (value >> 8) < 0x80) {
+        printf("#\\%c", (char)(value >> 8));
+    } else {
+        describe(stdout, value);
+    }
+}
+
+static void write_record(const uint64_t *record) {
+    int64_t first = INTEGER_OF(record[0]);
+    if (first < 0) {
+        if (-first & 1) {
+            printf("%lldi%lld", (long long)record[1], (long long)(-first >> 1));
+        } else {
+            printf("%lluu%lld", (unsigned long long)record[1], (long long)(-first >> 1));
+        }
+        return;
+    }
+    /* the names of variants are not known at run time, nor their arity: the heap records it,
+       and variants without fields are constants of a single word */
+    printf("#<variant %lld", (long long)first);
+    uint32_t size = is_object((uint64_t *)record) ? HEADER(record)->size : 1;
+    for (uint32_t i = 1; i < size; i++) {
+        putchar(' ');
+        write_value(record[i]);
+    }
+    putchar('>');
+}
+
+/* writes a value to stdout, as the REPL shows it */
+void cody_write(uint64_t value) {
+    write_value(value);
+    fflush(stdout);
+}
+
+/* ---------------------------------------------------------------------------------------- */
+/* forms of the REPL                                                                         */
+
+/* runs the code of a form, storing its value in *result and returning 1, or returning 0 if it
+   ended with an error, which was written to stderr. the form leaves the root stack as it found
+   it either way, and the continuations it captured cannot be resumed after it ends */
+int cody_run_form(uint64_t (*form)(void), uint64_t *result) {
+    jmp_buf buffer;
+    jmp_buf *outer = error_return;
+    uint64_t *root_top = cody_root_stack_top;
+    struct Continuation *continuations = active_continuations;
+    error_return = &buffer;
+    if (setjmp(buffer)) {
+        error_return = outer;
+        cody_root_stack_top = root_top;
+        while (active_continuations != continuations) {
+            active_continuations->active = 0;
+            active_continuations = active_continuations->next;
+        }
+        return 0;
+    }
+    *result = form();
+    error_return = outer;
+    fflush(stdout);
+    return 1;
+}
@@ -1,20 +1,81 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+
+use crate::compiler::emit::{Emit, Platform};
+use crate::compiler::optimize::Optimization;
 
 #[derive(Parser)]
-#[command(author = "s-kybound")] 
+#[command(author = "s-kybound")]
 #[command(version = "0.0.1")]
 #[command(about = "Cody language compiler", long_about = None)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
-    #[arg(short = 'i', long = "input")]
-    input_file: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(short = 'i', long = "input", required = true)]
+    input_file: Option<String>,
 
     #[arg(default_value = "a.out")]
     #[arg(short = 'o', long = "output")]
     output_file: String,
+
+    /// The kind of file to write
+    #[arg(long = "emit", value_enum, default_value_t = Emit::Exe)]
+    emit: Emit,
+
+    /// The target triple to compile for, the host's by default
+    #[arg(long = "target")]
+    target: Option<String>,
+
+    /// The CPU to compile for, a generic one by default
+    #[arg(long = "cpu")]
+    cpu: Option<String>,
+
+    /// The features of the CPU to enable or disable, such as +sse4.2,-avx
+    #[arg(long = "features")]
+    features: Option<String>,
+
+    /// The level to optimise the program at
+    #[arg(short = 'O', value_enum, default_value = "0", global = true)]
+    optimization: Optimization,
+
+    /// Skip static type checking, relying on the checks made at run time
+    #[arg(long = "dynamic", global = true)]
+    dynamic: bool,
 }
 
-pub fn read_args() -> (String, String) {
-    let args = Args::parse();
-    (args.input_file, args.output_file)
+#[derive(Subcommand)]
+enum Command {
+    /// Compile a program in memory and run it, exiting with its value
+    Run {
+        input_file: String,
+    },
+    /// Read forms one at a time, and run each of them
+    Repl,
+}
+
+/// What the compiler was asked to do.
+pub enum Action {
+    /// Compile a program into a file: the input file, the output file and its kind, and the
+    /// platform it is compiled for.
+    Compile(String, String, Emit, Platform),
+    /// Run a program without writing any file.
+    Run(String),
+    /// Start the REPL.
+    Repl,
 }
 
+/// Returns what to do, whether types are only checked at run time, and the level to optimise
+/// programs at.
+pub fn read_args() -> (Action, bool, Optimization) {
+    let args = Args::parse();
+    let action = match args.command {
+        Some(Command::Run { input_file }) => Action::Run(input_file),
+        Some(Command::Repl) => Action::Repl,
+        None => {
+            let platform = Platform { triple: args.target, cpu: args.cpu, features: args.features };
+            Action::Compile(args.input_file.expect("The input file is required."), args.output_file, args.emit, platform)
+        },
+    };
+    (action, args.dynamic, args.optimization)
+}
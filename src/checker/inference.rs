@@ -0,0 +1,828 @@
+//! Hindley–Milner type inference.
+//! Every expression is given a type made of type variables, which are unified as the uses of
+//! the expression constrain them. Definitions of functions and other values are generalized
+//! over the type variables left free, so that they can be used at different types.
+//!
+//! Pairs and lists share their values: a pair whose tail is a list is a list, and `()` is a list
+//! of any type. Data that is recursive in another way, such as a tree of pairs, must be declared
+//! as an enum.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::closure_converter::collect_globals;
+use crate::compiler::primitives::Primitive;
+use crate::error::{CodyError, ErrorCode, Span};
+use crate::parser::node_types::{ExpressionAST, FieldType, ForeignType, Variant};
+use crate::parser::token_types::{AtomBinary, BitType};
+
+use super::types::{Scheme, Type, TypeNames, TypeVariable};
+
+/// Why two types do not unify.
+enum Mismatch {
+    Different,
+    /// A type variable would have to stand for a type containing it.
+    Infinite(TypeVariable, Type),
+}
+
+/// The state of the type inference of a program.
+#[derive(Clone)]
+pub struct Inference {
+    /// The type each type variable stands for, once it is known.
+    bindings: Vec<Option<Type>>,
+    /// The level each type variable was created at, lowered as it is unified with the types of
+    /// enclosing definitions. A definition only generalizes the variables above its own level.
+    levels: Vec<usize>,
+    /// The number of definitions around the expression being inferred.
+    level: usize,
+    /// The number of functions around the expression being inferred.
+    function_depth: usize,
+    /// The types of the variables in scope.
+    environment: HashMap<String, Scheme>,
+    /// Globals whose definition has not been inferred yet, with the type variable standing for
+    /// their type, as functions may refer to globals defined after them.
+    undefined_globals: HashMap<String, TypeVariable>,
+    /// The globals used before their definition, which cannot be generalized.
+    used_early: HashSet<String>,
+    /// The variants of the enums declared, in order, with the types of their fields.
+    enums: HashMap<String, Vec<(String, Vec<Type>)>>,
+    /// The enum each variant in scope belongs to.
+    variants: HashMap<String, String>,
+}
+
+impl Inference {
+    pub fn new() -> Inference {
+        Inference {
+            bindings: Vec::new(),
+            levels: Vec::new(),
+            level: 0,
+            function_depth: 0,
+            environment: HashMap::new(),
+            undefined_globals: HashMap::new(),
+            used_early: HashSet::new(),
+            enums: HashMap::new(),
+            variants: HashMap::new(),
+        }
+    }
+
+    /// Infers the type of a program. Its top level definitions stay in scope for the programs
+    /// inferred after it, unless it is not well typed.
+    pub fn infer_program(&mut self, ast: &ExpressionAST) -> Result<Type, CodyError> {
+        let mut globals: HashSet<String> = HashSet::new();
+        collect_globals(ast, &mut globals);
+        for global in globals {
+            if !self.environment.contains_key(&global) {
+                let placeholder = self.fresh();
+                if let Type::Variable(variable) = placeholder {
+                    self.undefined_globals.insert(global, variable);
+                }
+            }
+        }
+
+        let environment = self.environment.clone();
+        let (enums, variants) = (self.enums.clone(), self.variants.clone());
+        let result = self.infer(ast);
+        self.undefined_globals.clear();
+        self.used_early.clear();
+        self.level = 0;
+        self.function_depth = 0;
+        match result {
+            Ok(program_type) => Ok(self.resolve_fully(&program_type)),
+            Err(error) => {
+                self.environment = environment;
+                self.enums = enums;
+                self.variants = variants;
+                Err(error)
+            }
+        }
+    }
+
+    fn infer(&mut self, expression: &ExpressionAST) -> Result<Type, CodyError> {
+        match expression {
+            // variables
+            ExpressionAST::VariableExpr(s, span) => self.lookup(s, *span),
+
+            // data
+            ExpressionAST::IntegerExpr(_) => Ok(Type::Int),
+            ExpressionAST::BitsExpr(_, bit_type) => Ok(bits(*bit_type)),
+            ExpressionAST::FloatExpr(_) => Ok(Type::Float),
+            ExpressionAST::BoolExpr(_) => Ok(Type::Bool),
+            ExpressionAST::CharExpr(_) => Ok(Type::Char),
+            ExpressionAST::StringExpr(_) => Ok(Type::Str),
+            ExpressionAST::SymbolExpr(_) => Ok(Type::Symbol),
+            ExpressionAST::NoneExpr => Ok(Type::List(Box::new(self.fresh()))),
+            ExpressionAST::PairExpr(head, tail) => {
+                let head: Type = self.infer(head)?;
+                let tail: Type = self.infer(tail)?;
+                Ok(Type::Pair(Box::new(head), Box::new(tail)))
+            },
+            ExpressionAST::FunctionExpr(params, body) => {
+                let environment = self.environment.clone();
+                let mut parameter_types: Vec<Type> = Vec::new();
+                for param in params {
+                    let parameter_type: Type = self.fresh();
+                    self.environment.insert(variable_name(param).to_string(), Scheme::mono(parameter_type.clone()));
+                    parameter_types.push(parameter_type);
+                }
+                self.function_depth += 1;
+                let result: Type = self.infer(body)?;
+                self.function_depth -= 1;
+                self.environment = environment;
+                Ok(Type::Function(parameter_types, Box::new(result)))
+            },
+            ExpressionAST::OperatorExpr(op) => Ok(self.operator_type(op)),
+            ExpressionAST::ClosureExpr(..) => panic!("Closures are converted after type inference."),
+            ExpressionAST::ContExpr(function, span) => {
+                let function_type: Type = self.infer(function)?;
+                let result: Type = self.fresh();
+                let continuation = Type::Function(vec![result.clone()], Box::new(self.fresh()));
+                let expected = Type::Function(vec![continuation], Box::new(result.clone()));
+                self.expect(&expected, &function_type, *span, |expected, found| {
+                    format!("cont must be given a function of type {}, but is given {}.", expected, found)
+                })?;
+                Ok(result)
+            },
+
+            // definitions
+            ExpressionAST::DefineExpr(var, val) => self.infer_definition(var, val),
+
+            // calls
+            ExpressionAST::CallExpr(function, arguments, span) => {
+                let function_type: Type = self.infer(function)?;
+                let mut argument_types: Vec<Type> = Vec::new();
+                for argument in arguments {
+                    argument_types.push(self.infer(argument)?);
+                }
+                let name = match function.as_ref() {
+                    ExpressionAST::VariableExpr(s, _) => s.as_str(),
+                    _ => "the called expression",
+                };
+                self.apply(name, function_type, argument_types, *span)
+            },
+
+            // conditionals
+            ExpressionAST::IfExpr(pred, conseq, alt, span) => {
+                let predicate: Type = self.infer(pred)?;
+                self.expect(&Type::Bool, &predicate, *span, |expected, found| {
+                    format!("The condition of if must be {}, but is {}.", expected, found)
+                })?;
+                let consequent: Type = self.infer(conseq)?;
+                let alternative: Type = self.infer(alt)?;
+                self.expect(&consequent, &alternative, *span, |expected, found| {
+                    format!("The branches of if must have the same type, but are {} and {}.", expected, found)
+                })?;
+                Ok(self.join(consequent, alternative))
+            },
+
+            // match case
+            ExpressionAST::MatchExpr(expr, arms, span) => self.infer_match(expr, arms, *span),
+            ExpressionAST::MatchArmExpr(..) | ExpressionAST::VariantExpr(..) => panic!("Match arms are inferred with their match expression."),
+
+            // sequence expressions
+            ExpressionAST::SeqExpr(seq) => {
+                // an empty sequence is 0
+                let mut last = Type::Int;
+                for expr in seq {
+                    last = self.infer(expr)?;
+                }
+                Ok(last)
+            },
+
+            // atomic binary expressions
+            ExpressionAST::AtomBinExpr(op, l, r, span) => {
+                let operator_type: Type = self.operator_type(op);
+                let operands: Vec<Type> = vec![self.infer(l)?, self.infer(r)?];
+                self.apply(op.symbol(), operator_type, operands, *span)
+            },
+
+            // external functions
+            ExpressionAST::ExternExpr(var, parameter_types, return_type) => {
+                let (name, span) = match var.as_ref() {
+                    ExpressionAST::VariableExpr(s, span) => (s, *span),
+                    _ => panic!("Expected variable name in extern expression.")
+                };
+                self.level += 1;
+                let parameters: Vec<Type> = parameter_types.iter().map(|parameter_type| foreign_type(*parameter_type)).collect();
+                let result: Type = match return_type {
+                    // void functions return ()
+                    ForeignType::Void => Type::List(Box::new(self.fresh())),
+                    return_type => foreign_type(*return_type),
+                };
+                self.level -= 1;
+                self.bind_definition(name, span, &Type::Function(parameters, Box::new(result)), true)
+            },
+
+            // enums
+            ExpressionAST::EnumExpr(var, variants) => self.infer_enum(var, variants),
+        }
+    }
+
+    /// Looks up the type of a variable, falling back on the primitive of that name.
+    fn lookup(&mut self, name: &str, span: Span) -> Result<Type, CodyError> {
+        if let Some(scheme) = self.environment.get(name).cloned() {
+            return Ok(self.instantiate(&scheme));
+        }
+        if let Some(placeholder) = self.undefined_globals.get(name).copied() {
+            self.used_early.insert(name.to_string());
+            return Ok(Type::Variable(placeholder));
+        }
+        match Primitive::from_name(name) {
+            Some(primitive) => Ok(self.primitive_type(primitive)),
+            None => Err(CodyError::new(ErrorCode::UnboundVariable, format!("Variable {} not found in scope.", name), span)),
+        }
+    }
+
+    fn infer_definition(&mut self, var: &ExpressionAST, val: &ExpressionAST) -> Result<Type, CodyError> {
+        let (name, span) = match var {
+            ExpressionAST::VariableExpr(s, span) => (s, *span),
+            _ => panic!("Expected variable name in define expression.")
+        };
+        self.level += 1;
+        let defined: Type = match val {
+            // a defined function refers to itself through its own closure
+            ExpressionAST::FunctionExpr(..) => {
+                let own: Type = self.fresh();
+                let previous: Option<Scheme> = self.environment.insert(name.clone(), Scheme::mono(own.clone()));
+                let defined: Type = self.infer(val)?;
+                match previous {
+                    Some(scheme) => self.environment.insert(name.clone(), scheme),
+                    None => self.environment.remove(name),
+                };
+                self.expect(&own, &defined, span, |expected, found| {
+                    format!("{} is used as {} in its own definition, but is defined as {}.", name, expected, found)
+                })?;
+                defined
+            },
+            val => self.infer(val)?,
+        };
+        self.level -= 1;
+        self.bind_definition(name, span, &defined, is_value(val))
+    }
+
+    /// Binds the name of a definition to its type, generalized if `generalize` is set, and
+    /// returns the type of the definition itself.
+    fn bind_definition(&mut self, name: &str, span: Span, defined: &Type, generalize: bool) -> Result<Type, CodyError> {
+        // a global used before its definition has the same type at the uses and the definition
+        if self.function_depth == 0 {
+            if let Some(placeholder) = self.undefined_globals.remove(name) {
+                if self.used_early.remove(name) {
+                    self.expect(&Type::Variable(placeholder), defined, span, |expected, found| {
+                        format!("{} is used as {} before its definition, but is defined as {}.", name, expected, found)
+                    })?;
+                }
+            }
+        }
+        let scheme: Scheme = if generalize {
+            self.generalize(defined)
+        } else {
+            // the type variables of a value computed at run time stay the same at every use
+            self.lower(defined, self.level);
+            Scheme::mono(self.resolve_fully(defined))
+        };
+        let defined: Type = self.instantiate(&scheme);
+        self.environment.insert(name.to_string(), scheme);
+        Ok(defined)
+    }
+
+    /// Declares an enum, binding each of its variants to the function building its values.
+    /// Declarations evaluate to `()`.
+    fn infer_enum(&mut self, var: &ExpressionAST, variants: &[Variant]) -> Result<Type, CodyError> {
+        let name: &str = variable_name(var);
+        // a declaration replaces the variants of an earlier enum of the same name, and the
+        // fields of a variant may hold values of the enum itself
+        self.variants.retain(|_, owner| owner != name);
+        self.enums.insert(name.to_string(), Vec::new());
+        let mut declared: Vec<(String, Vec<Type>)> = Vec::new();
+        for variant in variants {
+            let fields: Vec<Type> = variant.fields.iter()
+                .map(|field| self.field_type(field))
+                .collect::<Result<_, _>>()?;
+            let constructor = Type::Function(fields.clone(), Box::new(Type::Enum(name.to_string())));
+            self.environment.insert(variant.name.clone(), Scheme::mono(constructor));
+            self.variants.insert(variant.name.clone(), name.to_string());
+            declared.push((variant.name.clone(), fields));
+        }
+        self.enums.insert(name.to_string(), declared);
+        Ok(Type::List(Box::new(self.fresh())))
+    }
+
+    fn field_type(&self, field: &FieldType) -> Result<Type, CodyError> {
+        match field {
+            FieldType::List(element) => Ok(Type::List(Box::new(self.field_type(element)?))),
+            FieldType::Named(name, span) => match name.as_str() {
+                "int" => Ok(Type::Int),
+                "float" => Ok(Type::Float),
+                "bool" => Ok(Type::Bool),
+                "char" => Ok(Type::Char),
+                "string" => Ok(Type::Str),
+                "symbol" => Ok(Type::Symbol),
+                name if self.enums.contains_key(name) => Ok(Type::Enum(name.to_string())),
+                name if BitType::from_name(name).is_some() => Ok(bits(BitType::from_name(name).unwrap())),
+                name => Err(CodyError::new(ErrorCode::UnknownType, format!("Unknown type: {}", name), *span)),
+            },
+        }
+    }
+
+    /// Infers a match expression. A match on the values of an enum without a catch-all arm must
+    /// have an arm for each of its variants.
+    fn infer_match(&mut self, expr: &ExpressionAST, arms: &[ExpressionAST], span: Span) -> Result<Type, CodyError> {
+        let matched: Type = self.infer(expr)?;
+        let mut result: Option<Type> = None;
+        let mut catch_all = false;
+        let mut covered: HashSet<String> = HashSet::new();
+        for arm in arms {
+            let (patterns, body) = match arm {
+                ExpressionAST::MatchArmExpr(patterns, body) => (patterns, body),
+                _ => panic!("Expected match arm in match expression.")
+            };
+            let environment = self.environment.clone();
+            for pattern in patterns {
+                let pattern_type: Type = match pattern {
+                    ExpressionAST::IntegerExpr(_) => Type::Int,
+                    ExpressionAST::BoolExpr(_) => Type::Bool,
+                    ExpressionAST::CharExpr(_) => Type::Char,
+                    // a variant without fields is matched by (variant), where a bare name would
+                    // bind every value under the name of the variant
+                    ExpressionAST::VariableExpr(s, span) if self.variants.contains_key(s) => {
+                        return Err(CodyError::new(
+                            ErrorCode::InvalidPattern,
+                            format!("{} is a variant, which is matched by the pattern ({}).", s, s),
+                            *span
+                        ));
+                    },
+                    // an identifier pattern binds the matched value for the arm's body
+                    ExpressionAST::VariableExpr(s, _) => {
+                        self.environment.insert(s.clone(), Scheme::mono(matched.clone()));
+                        catch_all = true;
+                        continue;
+                    },
+                    // a variant pattern binds the fields of the variant
+                    ExpressionAST::VariantExpr(variant, fields) => {
+                        let (variant_name, variant_span) = match variant.as_ref() {
+                            ExpressionAST::VariableExpr(s, span) => (s, *span),
+                            _ => panic!("Expected variable name in variant pattern.")
+                        };
+                        let (enum_name, field_types) = self.variant(variant_name, variant_span)?;
+                        if field_types.len() != fields.len() {
+                            return Err(CodyError::new(
+                                ErrorCode::ArityMismatch,
+                                format!("{} has {} fields, but its pattern binds {}.", variant_name, field_types.len(), fields.len()),
+                                variant_span
+                            ));
+                        }
+                        for (field, field_type) in fields.iter().zip(field_types) {
+                            self.environment.insert(variable_name(field).to_string(), Scheme::mono(field_type));
+                        }
+                        covered.insert(variant_name.clone());
+                        Type::Enum(enum_name)
+                    },
+                    _ => panic!("Unexpected pattern in match arm: {:?}", pattern)
+                };
+                self.expect(&matched, &pattern_type, span, |expected, found| {
+                    format!("A pattern of match is {}, but the matched value is {}.", found, expected)
+                })?;
+            }
+            let body_type: Type = self.infer(body)?;
+            self.environment = environment;
+            result = Some(match result {
+                None => body_type,
+                Some(result) => {
+                    self.expect(&result, &body_type, span, |expected, found| {
+                        format!("The arms of match must have the same type, but are {} and {}.", expected, found)
+                    })?;
+                    self.join(result, body_type)
+                },
+            });
+        }
+
+        if let (false, Type::Enum(name)) = (catch_all, self.resolve(&matched)) {
+            let missing: Vec<&str> = self.enums[&name].iter()
+                .map(|(variant, _)| variant.as_str())
+                .filter(|variant| !covered.contains(*variant))
+                .collect();
+            if !missing.is_empty() {
+                let variants = if missing.len() == 1 { "variant" } else { "variants" };
+                return Err(CodyError::new(
+                    ErrorCode::NonExhaustiveMatch,
+                    format!("The arms of match do not cover the {} {} of {}.", variants, missing.join(", "), name),
+                    span
+                ));
+            }
+        }
+        Ok(result.unwrap_or_else(|| self.fresh()))
+    }
+
+    /// The enum a variant belongs to, and the types of its fields.
+    fn variant(&self, name: &str, span: Span) -> Result<(String, Vec<Type>), CodyError> {
+        let enum_name: &String = self.variants.get(name)
+            .ok_or_else(|| CodyError::new(ErrorCode::UnboundVariable, format!("Variant {} not found in scope.", name), span))?;
+        let (_, fields) = self.enums[enum_name].iter()
+            .find(|(variant, _)| variant == name)
+            .expect("Variants belong to a declared enum.");
+        Ok((enum_name.clone(), fields.clone()))
+    }
+
+    /// The type of the result of calling a function of the given type.
+    fn apply(&mut self, name: &str, function_type: Type, arguments: Vec<Type>, span: Span) -> Result<Type, CodyError> {
+        match self.resolve(&function_type) {
+            Type::Function(parameters, result) => {
+                if parameters.len() != arguments.len() {
+                    return Err(CodyError::new(
+                        ErrorCode::ArityMismatch,
+                        format!("{} expects {} arguments, but was called with {}.", name, parameters.len(), arguments.len()),
+                        span
+                    ));
+                }
+                for (i, (parameter, argument)) in parameters.iter().zip(&arguments).enumerate() {
+                    self.expect(parameter, argument, span, |expected, found| {
+                        format!("Argument {} of {} must be {}, but is {}.", i + 1, name, expected, found)
+                    })?;
+                }
+                Ok(*result)
+            },
+            Type::Variable(_) => {
+                let result: Type = self.fresh();
+                let called = Type::Function(arguments, Box::new(result.clone()));
+                self.expect(&function_type, &called, span, |expected, found| {
+                    format!("{} is called as {}, but is {}.", name, found, expected)
+                })?;
+                Ok(result)
+            },
+            other => Err(CodyError::new(
+                ErrorCode::TypeMismatch,
+                format!("Only functions can be called, but {} is {}.", name, self.resolve_fully(&other)),
+                span
+            )),
+        }
+    }
+
+    /// The type of a value that is either of two unified types, preferring a list to a pair,
+    /// since a list may also be `()`.
+    fn join(&self, first: Type, second: Type) -> Type {
+        match (self.resolve(&first), self.resolve(&second)) {
+            (Type::Pair(..), list @ Type::List(_)) => list,
+            _ => first,
+        }
+    }
+
+    /// Unifies the type a value is expected to have with the type it was found to have,
+    /// reporting a mismatch with the message built from both types.
+    fn expect(&mut self, expected: &Type, found: &Type, span: Span, message: impl FnOnce(&str, &str) -> String) -> Result<(), CodyError> {
+        let mismatch: Mismatch = match self.unify(expected, found) {
+            Ok(()) => return Ok(()),
+            Err(mismatch) => mismatch,
+        };
+        let mut names = TypeNames::default();
+        let expected: String = names.write(&self.resolve_fully(expected));
+        let found: String = names.write(&self.resolve_fully(found));
+        let mut message: String = message(&expected, &found);
+        if let Mismatch::Infinite(variable, t) = mismatch {
+            let variable: String = names.write(&Type::Variable(variable));
+            let t: String = names.write(&self.resolve_fully(&t));
+            message.push_str(&format!(" {} cannot be {}, which contains it.", variable, t));
+        }
+        Err(CodyError::new(ErrorCode::TypeMismatch, message, span))
+    }
+
+    fn unify(&mut self, first: &Type, second: &Type) -> Result<(), Mismatch> {
+        match (self.resolve(first), self.resolve(second)) {
+            (Type::Variable(x), Type::Variable(y)) if x == y => Ok(()),
+            (Type::Variable(variable), t) | (t, Type::Variable(variable)) => self.bind(variable, t),
+            (Type::List(x), Type::List(y)) | (Type::Bits(x), Type::Bits(y)) => self.unify(&x, &y),
+            (Type::Pair(head, tail), Type::Pair(other_head, other_tail)) => {
+                self.unify(&head, &other_head)?;
+                self.unify(&tail, &other_tail)
+            },
+            // lists are made of pairs
+            (Type::Pair(head, tail), Type::List(element)) | (Type::List(element), Type::Pair(head, tail)) => {
+                self.unify(&head, &element)?;
+                self.unify(&tail, &Type::List(element))
+            },
+            (Type::Function(parameters, result), Type::Function(other_parameters, other_result)) if parameters.len() == other_parameters.len() => {
+                for (parameter, other_parameter) in parameters.iter().zip(&other_parameters) {
+                    self.unify(parameter, other_parameter)?;
+                }
+                self.unify(&result, &other_result)
+            },
+            (first, second) if first == second => Ok(()),
+            _ => Err(Mismatch::Different),
+        }
+    }
+
+    /// Binds an unbound type variable to a type, unless the type contains the variable.
+    fn bind(&mut self, variable: TypeVariable, t: Type) -> Result<(), Mismatch> {
+        let t = if self.occurs(variable, &t) {
+            // a pair whose tail is the pair itself is a list, and other recursive types have to
+            // be declared as enums
+            match t {
+                Type::Pair(head, tail) if self.resolve(&tail) == Type::Variable(variable) && !self.occurs(variable, &head) => Type::List(head),
+                t => return Err(Mismatch::Infinite(variable, t)),
+            }
+        } else {
+            t
+        };
+        self.lower(&t, self.levels[variable]);
+        self.bindings[variable] = Some(t);
+        Ok(())
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        self.levels.push(self.level);
+        Type::Variable(self.bindings.len() - 1)
+    }
+
+    /// Follows the bindings of a type variable, up to a type that is not a bound variable.
+    fn resolve(&self, t: &Type) -> Type {
+        match t {
+            Type::Variable(variable) => match &self.bindings[*variable] {
+                Some(bound) => self.resolve(bound),
+                None => t.clone(),
+            },
+            t => t.clone(),
+        }
+    }
+
+    /// Replaces every bound type variable in a type with the type it stands for.
+    fn resolve_fully(&self, t: &Type) -> Type {
+        match self.resolve(t) {
+            Type::List(element) => Type::List(Box::new(self.resolve_fully(&element))),
+            Type::Bits(width) => Type::Bits(Box::new(self.resolve_fully(&width))),
+            Type::Pair(head, tail) => Type::Pair(Box::new(self.resolve_fully(&head)), Box::new(self.resolve_fully(&tail))),
+            Type::Function(parameters, result) => Type::Function(
+                parameters.iter().map(|parameter| self.resolve_fully(parameter)).collect(),
+                Box::new(self.resolve_fully(&result))
+            ),
+            t => t,
+        }
+    }
+
+    fn occurs(&self, variable: TypeVariable, t: &Type) -> bool {
+        match self.resolve(t) {
+            Type::Variable(other) => other == variable,
+            Type::List(element) | Type::Bits(element) => self.occurs(variable, &element),
+            Type::Pair(head, tail) => self.occurs(variable, &head) || self.occurs(variable, &tail),
+            Type::Function(parameters, result) => parameters.iter().any(|parameter| self.occurs(variable, parameter)) || self.occurs(variable, &result),
+            _ => false,
+        }
+    }
+
+    /// Lowers the level of the unbound type variables in a type to at most `level`.
+    fn lower(&mut self, t: &Type, level: usize) {
+        match self.resolve(t) {
+            Type::Variable(variable) => self.levels[variable] = self.levels[variable].min(level),
+            Type::List(element) | Type::Bits(element) => self.lower(&element, level),
+            Type::Pair(head, tail) => {
+                self.lower(&head, level);
+                self.lower(&tail, level);
+            },
+            Type::Function(parameters, result) => {
+                parameters.iter().for_each(|parameter| self.lower(parameter, level));
+                self.lower(&result, level);
+            },
+            _ => (),
+        }
+    }
+
+    /// Generalizes a type over its unbound type variables created inside the current definition.
+    fn generalize(&self, t: &Type) -> Scheme {
+        let body: Type = self.resolve_fully(t);
+        let mut variables: Vec<TypeVariable> = Vec::new();
+        self.collect_variables(&body, &mut variables);
+        variables.retain(|variable| self.levels[*variable] > self.level);
+        Scheme { variables, body }
+    }
+
+    fn collect_variables(&self, t: &Type, variables: &mut Vec<TypeVariable>) {
+        match t {
+            Type::Variable(variable) if !variables.contains(variable) => variables.push(*variable),
+            Type::List(element) | Type::Bits(element) => self.collect_variables(element, variables),
+            Type::Pair(head, tail) => {
+                self.collect_variables(head, variables);
+                self.collect_variables(tail, variables);
+            },
+            Type::Function(parameters, result) => {
+                parameters.iter().for_each(|parameter| self.collect_variables(parameter, variables));
+                self.collect_variables(result, variables);
+            },
+            _ => (),
+        }
+    }
+
+    /// Replaces the type variables a scheme is generalized over with fresh ones.
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let fresh: HashMap<TypeVariable, Type> = scheme.variables.iter()
+            .map(|variable| (*variable, self.fresh()))
+            .collect();
+        self.substitute(&scheme.body, &fresh)
+    }
+
+    fn substitute(&self, t: &Type, substitution: &HashMap<TypeVariable, Type>) -> Type {
+        match self.resolve(t) {
+            Type::Variable(variable) => substitution.get(&variable).cloned().unwrap_or(Type::Variable(variable)),
+            Type::List(element) => Type::List(Box::new(self.substitute(&element, substitution))),
+            Type::Bits(width) => Type::Bits(Box::new(self.substitute(&width, substitution))),
+            Type::Pair(head, tail) => Type::Pair(Box::new(self.substitute(&head, substitution)), Box::new(self.substitute(&tail, substitution))),
+            Type::Function(parameters, result) => Type::Function(
+                parameters.iter().map(|parameter| self.substitute(parameter, substitution)).collect(),
+                Box::new(self.substitute(&result, substitution))
+            ),
+            t => t,
+        }
+    }
+
+    fn operator_type(&mut self, op: &AtomBinary) -> Type {
+        let (parameter, result) = match op {
+            AtomBinary::Add | AtomBinary::Sub | AtomBinary::Mul | AtomBinary::Div
+                | AtomBinary::And | AtomBinary::Or | AtomBinary::Not => (Type::Int, Type::Int),
+            AtomBinary::Lt => (Type::Int, Type::Bool),
+            // values of any type are equal when they are the same value
+            AtomBinary::Eq => (self.fresh(), Type::Bool),
+            AtomBinary::FAdd | AtomBinary::FSub | AtomBinary::FMul | AtomBinary::FDiv => (Type::Float, Type::Float),
+            AtomBinary::FEq | AtomBinary::FLt => (Type::Float, Type::Bool),
+        };
+        Type::Function(vec![parameter.clone(), parameter], Box::new(result))
+    }
+
+    fn primitive_type(&mut self, primitive: Primitive) -> Type {
+        let (a, b) = (self.fresh(), self.fresh());
+        // bit-precise integers of any width, the same for every use in the type
+        let w = Type::Bits(Box::new(self.fresh()));
+        let (parameters, result) = match primitive {
+            Primitive::Cons => (vec![a.clone(), b.clone()], Type::Pair(Box::new(a), Box::new(b))),
+            Primitive::Car => (vec![Type::Pair(Box::new(a.clone()), Box::new(b))], a),
+            Primitive::Cdr => (vec![Type::Pair(Box::new(a), Box::new(b.clone()))], b),
+            Primitive::IsPair | Primitive::IsNil | Primitive::IsSymbol | Primitive::IsFloat
+                | Primitive::IsBoolean | Primitive::IsChar | Primitive::IsString => (vec![a], Type::Bool),
+            Primitive::IntToFloat => (vec![Type::Int], Type::Float),
+            Primitive::FloatToInt => (vec![Type::Float], Type::Int),
+            Primitive::Not => (vec![Type::Bool], Type::Bool),
+            Primitive::CharToInt => (vec![Type::Char], Type::Int),
+            Primitive::IntToChar => (vec![Type::Int], Type::Char),
+            Primitive::StringLength => (vec![Type::Str], Type::Int),
+            Primitive::StringRef => (vec![Type::Str, Type::Int], Type::Char),
+            Primitive::StringAppend => (vec![Type::Str, Type::Str], Type::Str),
+            Primitive::StringEqual | Primitive::StringLess => (vec![Type::Str, Type::Str], Type::Bool),
+            Primitive::IntegerToString => (vec![Type::Int], Type::Str),
+            Primitive::StringToInteger => (vec![Type::Str], Type::Int),
+            Primitive::Print => (vec![Type::Str], Type::List(Box::new(a))),
+            Primitive::BitArithmetic(..) | Primitive::BitDiv | Primitive::BitRem
+                | Primitive::BitAnd | Primitive::BitOr | Primitive::BitXor => (vec![w.clone(), w.clone()], w),
+            Primitive::BitNot => (vec![w.clone()], w),
+            Primitive::ShiftLeft | Primitive::ShiftRight => (vec![w.clone(), Type::Int], w),
+            Primitive::BitEqual | Primitive::BitLess => (vec![w.clone(), w], Type::Bool),
+            Primitive::BitsToInt => (vec![w], Type::Int),
+            // an integer, or a bit-precise integer of any type
+            Primitive::ToBits(bit_type) => (vec![a], bits(bit_type)),
+        };
+        Type::Function(parameters, Box::new(result))
+    }
+}
+
+fn bits(bit_type: BitType) -> Type {
+    Type::Bits(Box::new(Type::Width(bit_type)))
+}
+
+fn variable_name(variable: &ExpressionAST) -> &str {
+    match variable {
+        ExpressionAST::VariableExpr(s, _) => s,
+        _ => panic!("Expected variable name.")
+    }
+}
+
+/// The type of the cody value an external function takes or returns as a C type.
+fn foreign_type(foreign_type: ForeignType) -> Type {
+    match foreign_type {
+        ForeignType::I8 | ForeignType::I16 | ForeignType::I32 | ForeignType::I64 | ForeignType::Ptr => Type::Int,
+        ForeignType::F32 | ForeignType::F64 => Type::Float,
+        ForeignType::Void => unreachable!("void is only the return type of an external function."),
+    }
+}
+
+/// Whether an expression is a value, computed without running any code. Only the types of
+/// values are generalized, since a value computed at run time, for instance by a continuation,
+/// may be used again at another type.
+fn is_value(expression: &ExpressionAST) -> bool {
+    match expression {
+        ExpressionAST::PairExpr(head, tail) => is_value(head) && is_value(tail),
+        ExpressionAST::VariableExpr(..)
+            | ExpressionAST::IntegerExpr(_)
+            | ExpressionAST::BitsExpr(..)
+            | ExpressionAST::FloatExpr(_)
+            | ExpressionAST::BoolExpr(_)
+            | ExpressionAST::CharExpr(_)
+            | ExpressionAST::StringExpr(_)
+            | ExpressionAST::SymbolExpr(_)
+            | ExpressionAST::NoneExpr
+            | ExpressionAST::FunctionExpr(..)
+            | ExpressionAST::OperatorExpr(_) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::parser::parse;
+
+    fn infer(program: &str) -> Result<Type, CodyError> {
+        let ast = parse(program).expect("Failed to parse the program.");
+        Inference::new().infer_program(&ast)
+    }
+
+    /// The type of a well typed program, as it is written.
+    fn type_of(program: &str) -> String {
+        infer(program).expect("The program is not well typed.").to_string()
+    }
+
+    fn error(program: &str) -> CodyError {
+        infer(program).expect_err("The program is well typed.")
+    }
+
+    #[test]
+    fn definitions_are_generalized() {
+        assert_eq!(type_of("(define id (fn (x) x)) (define a (id 1)) (id true)"), "bool");
+        assert_eq!(type_of("(define pair-of (fn (x) [x . x])) (pair-of 1)"), "[int . int]");
+    }
+
+    #[test]
+    fn parameters_are_not_generalized() {
+        let error = error("(define id (fn (x) x)) ((fn (g) (seq (g 1) (g true))) id)");
+        assert_eq!(error.code, ErrorCode::TypeMismatch);
+        assert_eq!(error.message, "Argument 1 of g must be int, but is bool.");
+    }
+
+    #[test]
+    fn pairs_ending_in_nil_are_lists() {
+        assert_eq!(type_of("(if true [1 . [2 . ()]] ())"), "(list int)");
+        assert_eq!(type_of("(car [1 . [2 . ()]])"), "int");
+        assert_eq!(type_of("(define sum (fn (xs) (if (nil? xs) 0 ($+ (car xs) (sum (cdr xs)))))) (sum [1 . [2 . ()]])"), "int");
+    }
+
+    #[test]
+    fn list_elements_must_have_the_same_type() {
+        let error = error("(define sum (fn (xs) (if (nil? xs) 0 ($+ (car xs) (sum (cdr xs)))))) (sum [1 . [true . ()]])");
+        assert_eq!(error.code, ErrorCode::TypeMismatch);
+    }
+
+    #[test]
+    fn when_is_nil_whatever_its_body() {
+        assert_eq!(type_of("(if true (when false 1) (when true true \"done\"))"), "(list 'a)");
+    }
+
+    #[test]
+    fn branches_must_have_the_same_type() {
+        let error = error("(if true 1 false)");
+        assert_eq!(error.code, ErrorCode::TypeMismatch);
+        assert_eq!(error.span, Span::new(0, 17, 1, 1));
+    }
+
+    #[test]
+    fn types_cannot_contain_themselves() {
+        let error = error("(define f (fn (x) (x x)))");
+        assert_eq!(error.code, ErrorCode::TypeMismatch);
+        assert_eq!(error.message, "x is called as (fn ('a) 'b), but is 'a. 'a cannot be (fn ('a) 'b), which contains it.");
+        assert_eq!(error.span, Span::new(18, 23, 1, 19));
+    }
+
+    #[test]
+    fn match_must_cover_every_variant() {
+        let error = error("(enum shape (square int) (dot)) (match (dot) | (square s) -> s)");
+        assert_eq!(error.code, ErrorCode::NonExhaustiveMatch);
+        assert_eq!(error.message, "The arms of match do not cover the variant dot of shape.");
+        assert_eq!(type_of("(enum shape (square int) (dot)) (match (dot) | (square s) -> s | (dot) -> 0)"), "int");
+        assert_eq!(type_of("(enum shape (square int) (dot)) (match (dot) | (square s) -> s | _ -> 0)"), "int");
+    }
+
+    #[test]
+    fn variant_is_not_an_identifier_pattern() {
+        let error = error("(enum shape (square int) (dot)) (match (dot) | (square s) -> s | dot -> 0)");
+        assert_eq!(error.code, ErrorCode::InvalidPattern);
+        assert_eq!(error.message, "dot is a variant, which is matched by the pattern (dot).");
+        assert_eq!(error.span, Span::new(65, 68, 1, 66));
+    }
+
+    /// Every example is well typed, but for the one showing what a program compiled with
+    /// `--dynamic` may do.
+    #[test]
+    fn examples_are_well_typed() {
+        let examples: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let mut checked: usize = 0;
+        for directory in [examples.clone(), examples.join("bench")] {
+            for entry in fs::read_dir(&directory).expect("Failed to read the examples.") {
+                let path: PathBuf = entry.expect("Failed to read the examples.").path();
+                if path.extension().is_none_or(|extension| extension != "cdy") || path.ends_with("dynamic.cdy") {
+                    continue;
+                }
+                let program: String = fs::read_to_string(&path).expect("Failed to read the example.");
+                if let Err(error) = infer(&program) {
+                    panic!("{}", error.render(&program, &path.display().to_string()));
+                }
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+    }
+}
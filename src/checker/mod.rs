@@ -0,0 +1,17 @@
+//! Static type checking.
+//! Infers the type of every expression of a program before it is compiled, so that type errors
+//! are reported with the source they were found in before any code is generated.
+
+mod inference;
+pub mod types;
+
+use crate::error::CodyError;
+use crate::parser::node_types::ExpressionAST;
+
+pub use self::inference::Inference;
+use self::types::Type;
+
+/// Checks that a program is well typed, returning its type.
+pub fn check(ast: &ExpressionAST) -> Result<Type, CodyError> {
+    Inference::new().infer_program(ast)
+}
@@ -0,0 +1,97 @@
+//! The types of cody values.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::parser::token_types::BitType;
+
+/// A type variable, standing for a type that is not known yet.
+pub type TypeVariable = usize;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    Bool,
+    Char,
+    Str,
+    Symbol,
+    /// Lists of elements of the same type, built from pairs and ended by `()`.
+    List(Box<Type>),
+    Pair(Box<Type>, Box<Type>),
+    Function(Vec<Type>, Box<Type>), // parameter types and result type
+    /// Values of an enum declared by the program, named after it.
+    Enum(String),
+    /// Bit-precise integers of the type given by a width, so that operations on them can work
+    /// on integers of any width while requiring their operands to have the same one.
+    Bits(Box<Type>),
+    /// The width and signedness of bit-precise integers, such as `u8`.
+    Width(BitType),
+    Variable(TypeVariable),
+}
+
+/// A type generalized over some of its type variables, which every use of a definition
+/// replaces with fresh ones, so that a function such as `(fn (x) x)` can be used at any type.
+#[derive(Clone, Debug)]
+pub struct Scheme {
+    pub variables: Vec<TypeVariable>,
+    pub body: Type,
+}
+
+impl Scheme {
+    /// A type that is not generalized over any type variable.
+    pub fn mono(body: Type) -> Scheme {
+        Scheme { variables: Vec::new(), body }
+    }
+}
+
+/// Names the type variables of the types it writes `'a`, `'b`, ..., in order of appearance,
+/// so that types written with the same names share their variables.
+#[derive(Default)]
+pub struct TypeNames {
+    names: HashMap<TypeVariable, String>,
+}
+
+impl TypeNames {
+    fn name(&mut self, variable: TypeVariable) -> String {
+        let count = self.names.len();
+        self.names.entry(variable).or_insert_with(|| {
+            let letter = (b'a' + (count % 26) as u8) as char;
+            match count / 26 {
+                0 => format!("'{}", letter),
+                n => format!("'{}{}", letter, n),
+            }
+        }).clone()
+    }
+
+    /// Writes a type the way it is written in error messages.
+    pub fn write(&mut self, t: &Type) -> String {
+        match t {
+            Type::Int => "int".to_string(),
+            Type::Float => "float".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::Char => "char".to_string(),
+            Type::Str => "string".to_string(),
+            Type::Symbol => "symbol".to_string(),
+            Type::List(element) => format!("(list {})", self.write(element)),
+            Type::Pair(head, tail) => format!("[{} . {}]", self.write(head), self.write(tail)),
+            Type::Function(parameters, result) => {
+                let parameters: Vec<String> = parameters.iter().map(|parameter| self.write(parameter)).collect();
+                format!("(fn ({}) {})", parameters.join(" "), self.write(result))
+            },
+            Type::Enum(name) => name.clone(),
+            Type::Bits(width) => match width.as_ref() {
+                Type::Width(bit_type) => bit_type.to_string(),
+                width => format!("(bits {})", self.write(width)),
+            },
+            Type::Width(bit_type) => bit_type.to_string(),
+            Type::Variable(variable) => self.name(*variable),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", TypeNames::default().write(self))
+    }
+}
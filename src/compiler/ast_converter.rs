@@ -1,114 +1,893 @@
+use std::collections::HashSet;
+
+use inkwell::attributes::{Attribute, AttributeLoc};
 use inkwell::context::Context;
-use inkwell::module::Module;
+use inkwell::basic_block::BasicBlock;
+use inkwell::module::{Linkage, Module};
 use inkwell::builder::Builder;
-use inkwell::types::{FunctionType, BasicType, IntType};
-use inkwell::values::{FunctionValue, BasicValue, GenericValue, IntValue, AsValueRef, PointerValue};
+use inkwell::types::{BasicMetadataTypeEnum, FunctionType};
+use inkwell::values::{BasicMetadataValueEnum, CallSiteValue, FloatValue, FunctionValue, IntValue, PointerValue};
 
+use crate::error::{CodyError, ErrorCode, Span};
 use crate::parser::node_types::ExpressionAST;
 use crate::parser::token_types::AtomBinary;
 
-use super::scope::{Scope, self};
+use super::bits;
+use super::enums::{self, Variant};
+use super::foreign::{self, ForeignFunction};
+use super::primitives::{self, Primitive};
+use super::scope::Scope;
+use super::value::{self, word_type, pointer_type, CLOSURE_TAG};
 
 pub trait Codegen {
-    fn codegen<'a>(self, context: &'a Context, builder: &'a Builder<'a>, scope: &'a Scope<'a>) -> IntValue<'a>;
+    fn codegen<'a>(self, context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, scope: &Scope<'_, 'a>) -> Result<IntValue<'a>, CodyError>;
+
+    /// Generates an expression in tail position, returning its value from the current function.
+    /// Calls in tail position are tail calls, so that recursive loops run in constant stack.
+    fn codegen_tail<'a>(self, context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, scope: &Scope<'_, 'a>) -> Result<(), CodyError>;
+}
+
+/// The calling convention of cody functions, LLVM's `tailcc`, which guarantees that calls
+/// marked `tail` in tail position reuse the caller's stack frame.
+const TAIL_CALL_CONVENTION: u32 = 18;
+
+/// LLVM's `fastcc`, used by cody functions on targets without `tailcc`.
+const FAST_CALL_CONVENTION: u32 = 8;
+
+/// The calling convention of cody functions on the target of a module. The RISC-V and WebAssembly
+/// backends do not support `tailcc`, so calls in tail position there only reuse the caller's stack
+/// frame when LLVM manages to turn them into jumps, and a loop of them may overflow the stack.
+fn call_convention(module: &Module<'_>) -> u32 {
+    let triple = module.get_triple();
+    let triple = triple.as_str().to_string_lossy();
+    if triple.starts_with("riscv") || triple.starts_with("wasm") {
+        FAST_CALL_CONVENTION
+    } else {
+        TAIL_CALL_CONVENTION
+    }
 }
 
 impl Codegen for ExpressionAST {
-    fn codegen<'a>(self, context: &'a Context, builder: &'a Builder<'a>, scope: &'a Scope<'a>) -> IntValue<'a> {
-        match self {
+    fn codegen<'a>(self, context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, scope: &Scope<'_, 'a>) -> Result<IntValue<'a>, CodyError> {
+        let value: IntValue<'a> = match self {
             // variables
-            ExpressionAST::VariableExpr(s) => {
+            // external functions used as values are wrapped in a function converting their arguments
+            ExpressionAST::VariableExpr(s, _) if scope.get_extern(&s).is_some() => {
+                let foreign_function: ForeignFunction<'a> = scope.get_extern(&s).unwrap();
+                foreign_closure(context, module, builder, &s, &foreign_function)
+            },
+            // as are the constructors of variants, in a function building their values
+            ExpressionAST::VariableExpr(s, _) if scope.get_variant(&s).is_some() => {
+                let variant: Variant = scope.get_variant(&s).unwrap();
+                let function: FunctionValue<'a> = primitive_function(context, module, builder, &format!("cody.construct.{}", variant.number), variant.arity, |fields| {
+                    enums::build_value(context, module, builder, variant, fields)
+                });
+                static_closure(context, module, function)
+            },
+            ExpressionAST::VariableExpr(s, span) => {
                 match scope.get_variable(&s) {
-                    Some(v) => builder.build_load(context.i32_type() ,v, &s)
-                    .expect("Failed to load variable.")
-                    .into_int_value(),
-                    None => panic!("Variable {} not found in scope.", s)
+                    Some((v, None)) => value::load_slot(context, builder, v, &s),
+                    Some((v, Some(owner))) if owner == scope.function => value::load_slot(context, builder, v, &s),
+                    Some(_) => panic!("Variable {} belongs to an enclosing function, closures must be converted before codegen.", s),
+                    // primitives can be shadowed by variables of the same name
+                    None => match Primitive::from_name(&s) {
+                        Some(primitive) => {
+                            let function: FunctionValue<'a> = primitive_function(context, module, builder, &primitive.function_name(), primitive.arity(), |arguments| {
+                                primitive.build(context, module, builder, arguments)
+                            });
+                            static_closure(context, module, function)
+                        },
+                        None => return Err(CodyError::new(ErrorCode::UnboundVariable, format!("Variable {} not found in scope.", s), span))
+                    }
                 }
             },
 
             // values
-            ExpressionAST::IntegerExpr(i) => context.i32_type().const_int(i as u64, false),
-            // ExpressionAST::NoneExpr => context.i32_type().ptr_type(inkwell::AddressSpace::Generic).const_null(),
-            // ExpressionAST::PairExpr(_, _) => ,
-            // ExpressionAST::FunctionExpr(_, _) => context.i32_type().const_int(42, false).as_basic_value(),
+            ExpressionAST::IntegerExpr(i) => value::integer(context, i as i64),
+            ExpressionAST::BitsExpr(extended, bit_type) => bits::constant(context, module, bit_type, extended),
+            ExpressionAST::FloatExpr(f) => value::float(context, module, f),
+            ExpressionAST::BoolExpr(b) => value::boolean(context, b),
+            ExpressionAST::CharExpr(c) => value::character(context, c),
+            ExpressionAST::StringExpr(s) => value::string(context, module, s.as_str()),
+            ExpressionAST::NoneExpr => value::nil(context),
+            ExpressionAST::SymbolExpr(name) => value::symbol(context, module, name.as_str()),
+            ExpressionAST::PairExpr(head, tail) => {
+                let values: Vec<IntValue<'a>> = codegen_all(context, module, builder, scope, vec![*head, *tail])?;
+                primitives::build_pair(context, module, builder, values[0], values[1])
+            },
+            ExpressionAST::OperatorExpr(op) => {
+                let function: FunctionValue<'a> = primitive_function(context, module, builder, operator_function_name(&op), 2, |arguments| {
+                    build_atomic_binary(context, module, builder, op.clone(), arguments[0], arguments[1])
+                });
+                static_closure(context, module, function)
+            },
+            // functions are closure converted before codegen
+            closure @ ExpressionAST::ClosureExpr(..) => build_closure(context, module, builder, scope, None, closure)?.1,
 
             // definitions
             ExpressionAST::DefineExpr(var, val) => {
                 let var_name = match *var {
-                    ExpressionAST::VariableExpr(s) => s,
+                    ExpressionAST::VariableExpr(s, _) => s,
                     _ => panic!("Expected variable name in define expression.")
                 };
-                let val_value: IntValue<'a> = val.codegen(context, builder, scope);
-                let val_type: IntType<'a> = val_value.get_type();
-                let var_value: PointerValue<'a> = builder.build_alloca(val_type, var_name.as_str())
-                    .expect("Failed to allocate variable");
-                builder.build_store(var_value, val_value);
-                scope.add_variable(var_name, var_value);
+                // top level definitions become module globals, so that functions can refer to them
+                let var_value: PointerValue<'a> = if scope.is_global() {
+                    value::build_global_slot(context, module, builder, var_name.as_str())
+                } else {
+                    value::build_slot(context, builder, var_name.as_str())
+                };
+                // functions are named after their definition, and refer to themselves through their own closure
+                let val_value: IntValue<'a> = match *val {
+                    closure @ ExpressionAST::ClosureExpr(..) => {
+                        let (function, closure_value) = build_closure(context, module, builder, scope, Some(var_name.as_str()), closure)?;
+                        scope.add_function(var_name, var_value, function);
+                        closure_value
+                    },
+                    val => {
+                        let val_value: IntValue<'a> = val.codegen(context, module, builder, scope)?;
+                        scope.add_variable(var_name, var_value);
+                        val_value
+                    }
+                };
+                value::store_slot(builder, var_value, val_value);
                 val_value
             },
 
             // calls
-            // ExpressionAST::CallExpr(_, _) => context.i32_type().const_int(42, false).as_basic_value(),
-            
+            // calls to external functions are checked against their signature
+            ExpressionAST::CallExpr(function, arguments, _) if extern_call(&function, scope).is_some() => {
+                let foreign_function: ForeignFunction<'a> = extern_call(&function, scope).unwrap();
+                let (name, span) = match *function {
+                    ExpressionAST::VariableExpr(s, span) => (s, span),
+                    _ => unreachable!()
+                };
+                foreign::check_call(&name, span, &foreign_function, &arguments)?;
+                let argument_values: Vec<IntValue<'a>> = codegen_all(context, module, builder, scope, arguments)?;
+                foreign::build_call(context, module, builder, &foreign_function, &argument_values)
+            },
+            // calls to the constructors of variants are generated inline
+            ExpressionAST::CallExpr(function, arguments, span) if variant_call(&function, scope).is_some() => {
+                let variant: Variant = variant_call(&function, scope).unwrap();
+                if variant.arity != arguments.len() {
+                    let name = match *function {
+                        ExpressionAST::VariableExpr(s, _) => s,
+                        _ => unreachable!()
+                    };
+                    return Err(CodyError::new(
+                        ErrorCode::ArityMismatch,
+                        format!("Variant {} has {} fields, but was given {}.", name, variant.arity, arguments.len()),
+                        span
+                    ));
+                }
+                let field_values: Vec<IntValue<'a>> = codegen_all(context, module, builder, scope, arguments)?;
+                enums::build_value(context, module, builder, variant, &field_values)
+            },
+            // calls to primitives that are not shadowed by a variable are generated inline
+            ExpressionAST::CallExpr(function, arguments, _) if primitive_call(&function, scope).is_some() => {
+                let primitive: Primitive = primitive_call(&function, scope).unwrap();
+                if primitive.arity() != arguments.len() {
+                    let span = match *function {
+                        ExpressionAST::VariableExpr(_, span) => span,
+                        _ => unreachable!()
+                    };
+                    return Err(CodyError::new(
+                        ErrorCode::ArityMismatch,
+                        format!("Primitive {:?} expects {} arguments, but was called with {}.", primitive, primitive.arity(), arguments.len()),
+                        span
+                    ));
+                }
+                let argument_values: Vec<IntValue<'a>> = codegen_all(context, module, builder, scope, arguments)?;
+                primitive.build(context, module, builder, &argument_values)
+            },
+            ExpressionAST::CallExpr(function, arguments, _) => build_call(context, module, builder, scope, *function, arguments, false)?,
+
             // conditionals
-            ExpressionAST::IfExpr(pred, conseq, alt) => {
-                let pred_value: IntValue<'a> = pred.codegen(context, builder, scope);
-                let pred_type: IntType<'a> = pred_value.get_type();
-                let pred_bool_value: IntValue<'a> = builder.build_int_compare(inkwell::IntPredicate::NE, pred_value, pred_type.const_int(0, false), "ifcond")
-                    .expect("Failed to build if condition.");
+            ExpressionAST::IfExpr(pred, conseq, alt, _) => {
+                let (then_block, else_block) = build_condition(context, module, builder, scope, *pred)?;
                 let function: FunctionValue<'a> = builder.get_insert_block().unwrap().get_parent().unwrap();
-                let then_block: inkwell::basic_block::BasicBlock = context.append_basic_block(function, "then");
-                let else_block: inkwell::basic_block::BasicBlock = context.append_basic_block(function, "else");
                 let merge_block: inkwell::basic_block::BasicBlock = context.append_basic_block(function, "ifcont");
-                builder.build_conditional_branch(pred_bool_value, then_block, else_block);
                 builder.position_at_end(then_block);
-                let conseq_value: IntValue<'a> = conseq.codegen(context, builder, scope);
-                builder.build_unconditional_branch(merge_block);
+                let conseq_value: IntValue<'a> = conseq.codegen(context, module, builder, scope)?;
+                builder.build_unconditional_branch(merge_block).expect("Failed to build branch.");
                 let then_block: inkwell::basic_block::BasicBlock = builder.get_insert_block().unwrap();
                 builder.position_at_end(else_block);
-                let alt_value: IntValue<'a> = alt.codegen(context, builder, scope);
-                builder.build_unconditional_branch(merge_block);
+                let alt_value: IntValue<'a> = alt.codegen(context, module, builder, scope)?;
+                builder.build_unconditional_branch(merge_block).expect("Failed to build branch.");
                 let else_block: inkwell::basic_block::BasicBlock = builder.get_insert_block().unwrap();
                 builder.position_at_end(merge_block);
-                let phi_node: inkwell::values::PhiValue<'a> = builder.build_phi(context.i32_type(), "iftmp").unwrap();
+                let phi_node: inkwell::values::PhiValue<'a> = builder.build_phi(word_type(context), "iftmp").unwrap();
                 phi_node.add_incoming(&[(&conseq_value, then_block), (&alt_value, else_block)]);
                 phi_node.as_basic_value().into_int_value()
             },
 
             // match case
-            // ExpressionAST::MatchExpr(_, _) => context.i32_type().const_int(42, false).as_basic_value(),
-            // ExpressionAST::MatchArmExpr(_, _) => context.i32_type().const_int(42, false).as_basic_value(),
+            ExpressionAST::MatchExpr(expr, arms, span) => {
+                let (match_value, match_arms) = build_match_dispatch(context, module, builder, scope, *expr, arms, span)?;
+                let function: FunctionValue<'a> = builder.get_insert_block().unwrap().get_parent().unwrap();
+                let merge_block: BasicBlock<'a> = context.append_basic_block(function, "matchcont");
+
+                let mut incoming: Vec<(IntValue<'a>, BasicBlock<'a>)> = Vec::new();
+                for arm in match_arms {
+                    builder.position_at_end(arm.block);
+                    let arm_scope = match_arm_scope(context, builder, scope, arm.binding, arm.fields, match_value);
+                    let arm_value: IntValue<'a> = arm.body.codegen(context, module, builder, &arm_scope)?;
+                    builder.build_unconditional_branch(merge_block).expect("Failed to build branch.");
+                    incoming.push((arm_value, builder.get_insert_block().unwrap()));
+                }
+
+                builder.position_at_end(merge_block);
+                let phi_node: inkwell::values::PhiValue<'a> = builder.build_phi(word_type(context), "matchtmp").unwrap();
+                for (value, block) in &incoming {
+                    phi_node.add_incoming(&[(value, *block)]);
+                }
+                phi_node.as_basic_value().into_int_value()
+            },
 
             // sequence expressions
             ExpressionAST::SeqExpr(seq) => {
-                let mut last = context.i32_type().const_int(0, false);
+                let mut last = word_type(context).const_int(0, false);
                 for expr in seq {
-                    last = expr.codegen(context, builder, scope);
+                    last = expr.codegen(context, module, builder, scope)?;
                 }
                 last
             },
 
+            // continuations
+            ExpressionAST::ContExpr(function, _) => {
+                let function_value: IntValue<'a> = function.codegen(context, module, builder, scope)?;
+                build_continuation(context, module, builder, function_value)
+            },
+
             // atomic binary expressions
-            ExpressionAST::AtomBinExpr(op, l, r) => {
-                let left = l.codegen(context, builder, scope);
-                let right = r.codegen(context, builder, scope);
-                match op {
-                    AtomBinary::Add => builder.build_int_add(left, right, "add"),
-                    AtomBinary::Sub => builder.build_int_sub(left, right, "sub"),
-                    AtomBinary::Mul => builder.build_int_mul(left, right, "mul"),
-                    AtomBinary::Div => builder.build_int_signed_div(left, right, "div"),
-                    AtomBinary::And => builder.build_and(left, right, "and"),
-                    AtomBinary::Or => builder.build_or(left, right, "or"),
-                    AtomBinary::Not => builder.build_not(left, "not"),
-                    AtomBinary::Eq => builder.build_int_compare(inkwell::IntPredicate::EQ, left, right, "eq"),
-                    AtomBinary::Lt => builder.build_int_compare(inkwell::IntPredicate::SLT, left, right, "lt"),
-                }.expect("Failed to build binary expression.")
+            // floats are on the heap, so the left operand is kept alive while the right one is generated
+            ExpressionAST::AtomBinExpr(op, l, r, _) if op.is_float() => {
+                let values: Vec<IntValue<'a>> = codegen_all(context, module, builder, scope, vec![*l, *r])?;
+                build_atomic_binary(context, module, builder, op, values[0], values[1])
+            },
+            // the operands of the other operators are integers, which the collector does not need to keep alive
+            ExpressionAST::AtomBinExpr(op, l, r, _) => {
+                let left = l.codegen(context, module, builder, scope)?;
+                let right = r.codegen(context, module, builder, scope)?;
+                build_atomic_binary(context, module, builder, op, left, right)
             },
 
             // external functions
-            //ExpressionAST::ExternExpr(_) => context.i32_type().const_int(42, false).as_basic_value(),
+            ExpressionAST::ExternExpr(var, parameter_types, return_type) => {
+                let (name, span) = match *var {
+                    ExpressionAST::VariableExpr(s, span) => (s, span),
+                    _ => panic!("Expected variable name in extern expression.")
+                };
+                let foreign_function: ForeignFunction<'a> = foreign::declare(context, module, &name, parameter_types, return_type, span)?;
+                let closure_value: IntValue<'a> = foreign_closure(context, module, builder, &name, &foreign_function);
+                scope.add_extern(name, foreign_function);
+                closure_value
+            },
 
-            _ => panic!("Expression not supported as of version 0.0.1: {:?}", self)
+            // enums
+            // declaring an enum binds the constructors of its variants, and evaluates to ()
+            ExpressionAST::EnumExpr(_, variants) => {
+                for variant in variants {
+                    scope.add_variant(variant.name, variant.fields.len());
+                }
+                value::nil(context)
+            },
+
+            // closure conversion turns every function into a closure, and arms and variant patterns
+            // only appear inside a match, which generates them itself
+            ExpressionAST::FunctionExpr(..) | ExpressionAST::MatchArmExpr(..) | ExpressionAST::VariantExpr(..) =>
+                unreachable!("{:?} is not an expression after closure conversion", self),
+        };
+        Ok(value)
+    }
+
+    fn codegen_tail<'a>(self, context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, scope: &Scope<'_, 'a>) -> Result<(), CodyError> {
+        match self {
+            // primitives and constructors are generated inline, and external functions are called
+            // with the C calling convention, so only calls to cody functions become tail calls
+            ExpressionAST::CallExpr(function, arguments, _) if primitive_call(&function, scope).is_none()
+                && variant_call(&function, scope).is_none()
+                && extern_call(&function, scope).is_none() => {
+                let call_value: IntValue<'a> = build_call(context, module, builder, scope, *function, arguments, true)?;
+                builder.build_return(Some(&call_value)).expect("Failed to build return.");
+            },
+
+            // both branches of a conditional are in tail position
+            ExpressionAST::IfExpr(pred, conseq, alt, _) => {
+                let (then_block, else_block) = build_condition(context, module, builder, scope, *pred)?;
+                builder.position_at_end(then_block);
+                conseq.codegen_tail(context, module, builder, scope)?;
+                builder.position_at_end(else_block);
+                alt.codegen_tail(context, module, builder, scope)?;
+            },
+
+            // as is the body of every match arm
+            ExpressionAST::MatchExpr(expr, arms, span) => {
+                let (match_value, match_arms) = build_match_dispatch(context, module, builder, scope, *expr, arms, span)?;
+                for arm in match_arms {
+                    builder.position_at_end(arm.block);
+                    let arm_scope = match_arm_scope(context, builder, scope, arm.binding, arm.fields, match_value);
+                    arm.body.codegen_tail(context, module, builder, &arm_scope)?;
+                }
+            },
+
+            // and the last expression of a sequence
+            ExpressionAST::SeqExpr(mut seq) => match seq.pop() {
+                Some(last) => {
+                    for expr in seq {
+                        expr.codegen(context, module, builder, scope)?;
+                    }
+                    last.codegen_tail(context, module, builder, scope)?;
+                },
+                None => build_return(context, module, builder, word_type(context).const_int(0, false)),
+            },
+
+            expression => {
+                let value: IntValue<'a> = expression.codegen(context, module, builder, scope)?;
+                build_return(context, module, builder, value);
+            }
         }
+        Ok(())
+    }
+}
+
+/// Builds a call to a function value.
+/// The code of a tail call pops the caller's frame off the root stack before jumping to the callee.
+fn build_call<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, scope: &Scope<'_, 'a>, function: ExpressionAST, arguments: Vec<ExpressionAST>, tail: bool) -> Result<IntValue<'a>, CodyError> {
+    // calls through a variable known to hold a function are direct, and their arity is checked
+    let known_function: Option<FunctionValue<'a>> = match &function {
+        ExpressionAST::VariableExpr(s, span) => match scope.get_function(s) {
+            Some(f) if f.count_params() as usize - 1 != arguments.len() => return Err(CodyError::new(
+                ErrorCode::ArityMismatch,
+                format!("Function {} expects {} arguments, but was called with {}.", s, f.count_params() - 1, arguments.len()),
+                *span
+            )),
+            known_function => known_function
+        },
+        _ => None
+    };
+    // the closure is passed to its code as the first argument
+    let mut expressions: Vec<ExpressionAST> = vec![function];
+    expressions.extend(arguments);
+    let values: Vec<IntValue<'a>> = codegen_all(context, module, builder, scope, expressions)?;
+    let closure_value: IntValue<'a> = values[0];
+    let argument_values: Vec<BasicMetadataValueEnum<'a>> = values.into_iter().map(|value| value.into()).collect();
+    if tail {
+        value::leave_frame(context, module, builder);
     }
-}
\ No newline at end of file
+    let call_value: CallSiteValue<'a> = match known_function {
+        Some(f) => builder.build_call(f, &argument_values, "call").expect("Failed to build call."),
+        None => build_closure_call(context, module, builder, closure_value, &argument_values),
+    };
+    call_value.set_call_convention(call_convention(module));
+    call_value.set_tail_call(tail);
+    Ok(call_value.try_as_basic_value().left()
+        .expect("Call did not produce a value.")
+        .into_int_value())
+}
+
+/// Calls the code of a closure whose function is not known statically, aborting the program
+/// with a type error if the value called is not a closure, and with an error if the closure
+/// takes another number of arguments than it is called with.
+/// The arguments start with the closure itself.
+fn build_closure_call<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, closure_value: IntValue<'a>, argument_values: &[BasicMetadataValueEnum<'a>]) -> CallSiteValue<'a> {
+    value::check_tag(context, module, builder, closure_value, CLOSURE_TAG, "a call", "a function");
+    let closure_pointer: PointerValue<'a> = value::untag_pointer(context, builder, closure_value);
+    let arity: usize = argument_values.len() - 1;
+    let arity_value: IntValue<'a> = value::load_field(context, builder, closure_pointer, 1, "arity");
+    let same_arity: IntValue<'a> = builder.build_int_compare(inkwell::IntPredicate::EQ, arity_value, value::integer(context, arity as i64), "samearity")
+        .expect("Failed to compare arity.");
+    value::build_error_check(context, module, builder, same_arity, &format!("function called with {} arguments takes a different number of them", arity));
+    let code_value: IntValue<'a> = value::load_field(context, builder, closure_pointer, 0, "code");
+    let code_pointer: PointerValue<'a> = builder.build_int_to_ptr(code_value, pointer_type(context), "code")
+        .expect("Failed to convert code to a pointer.");
+    builder.build_indirect_call(function_type(context, argument_values.len()), code_pointer, argument_values, "call")
+        .expect("Failed to build call.")
+}
+
+/// Calls a function with the current continuation, which can be resumed until the call returns.
+/// Resuming the continuation with a value makes the call return that value at once.
+/// The runtime captures continuations with setjmp, and resumes them with longjmp.
+fn build_continuation<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, function_value: IntValue<'a>) -> IntValue<'a> {
+    let function_slot: PointerValue<'a> = value::spill(context, builder, function_value);
+    let word_to_word: FunctionType<'a> = word_type(context).fn_type(&[word_type(context).into()], false);
+
+    let continuation_new: FunctionValue<'a> = value::runtime_function(module, "cody_continuation_new", word_to_word);
+    let code: IntValue<'a> = function_address(context, builder, continue_function(context, module, builder));
+    let continuation_value: IntValue<'a> = build_runtime_call(builder, continuation_new, &[code.into()], "continuation");
+    let continuation_slot: PointerValue<'a> = value::spill(context, builder, continuation_value);
+
+    let continuation_buffer: FunctionValue<'a> = value::runtime_function(module, "cody_continuation_buffer", pointer_type(context).fn_type(&[word_type(context).into()], false));
+    let buffer: PointerValue<'a> = builder.build_call(continuation_buffer, &[continuation_value.into()], "buffer")
+        .expect("Failed to build call.")
+        .try_as_basic_value().left()
+        .expect("Call did not produce a value.")
+        .into_pointer_value();
+    let setjmp: FunctionValue<'a> = value::runtime_function(module, "_setjmp", context.i32_type().fn_type(&[pointer_type(context).into()], false));
+    setjmp.add_attribute(AttributeLoc::Function, context.create_enum_attribute(Attribute::get_named_enum_kind_id("returns_twice"), 0));
+    let jumped: IntValue<'a> = builder.build_call(setjmp, &[buffer.into()], "jumped")
+        .expect("Failed to build call.")
+        .try_as_basic_value().left()
+        .expect("Call did not produce a value.")
+        .into_int_value();
+    let resumed: IntValue<'a> = builder.build_int_compare(inkwell::IntPredicate::NE, jumped, context.i32_type().const_zero(), "resumed")
+        .expect("Failed to compare setjmp result.");
+
+    let function: FunctionValue<'a> = builder.get_insert_block().unwrap().get_parent().unwrap();
+    let call_block: BasicBlock<'a> = context.append_basic_block(function, "contcall");
+    let resume_block: BasicBlock<'a> = context.append_basic_block(function, "contresume");
+    let merge_block: BasicBlock<'a> = context.append_basic_block(function, "contcont");
+    builder.build_conditional_branch(resumed, resume_block, call_block).expect("Failed to build branch.");
+
+    // the values are reloaded from their slots, as registers do not survive the jump back
+    builder.position_at_end(call_block);
+    let function_value: IntValue<'a> = value::load_slot(context, builder, function_slot, "function");
+    let continuation_value: IntValue<'a> = value::load_slot(context, builder, continuation_slot, "continuation");
+    let call_value: CallSiteValue<'a> = build_closure_call(context, module, builder, function_value, &[function_value.into(), continuation_value.into()]);
+    call_value.set_call_convention(call_convention(module));
+    let returned_value: IntValue<'a> = call_value.try_as_basic_value().left()
+        .expect("Call did not produce a value.")
+        .into_int_value();
+    let continuation_end: FunctionValue<'a> = value::runtime_function(module, "cody_continuation_end", context.void_type().fn_type(&[word_type(context).into()], false));
+    builder.build_call(continuation_end, &[continuation_value.into()], "").expect("Failed to build call.");
+    builder.build_unconditional_branch(merge_block).expect("Failed to build branch.");
+    let call_block: BasicBlock<'a> = builder.get_insert_block().unwrap();
+
+    builder.position_at_end(resume_block);
+    let continuation_value: IntValue<'a> = value::load_slot(context, builder, continuation_slot, "continuation");
+    let continuation_result: FunctionValue<'a> = value::runtime_function(module, "cody_continuation_value", word_to_word);
+    let resumed_value: IntValue<'a> = build_runtime_call(builder, continuation_result, &[continuation_value.into()], "resumed");
+    builder.build_unconditional_branch(merge_block).expect("Failed to build branch.");
+
+    builder.position_at_end(merge_block);
+    let phi_node: inkwell::values::PhiValue<'a> = builder.build_phi(word_type(context), "conttmp").unwrap();
+    phi_node.add_incoming(&[(&returned_value, call_block), (&resumed_value, resume_block)]);
+    phi_node.as_basic_value().into_int_value()
+}
+
+/// Returns the code of continuations, which resumes the continuation it is called through.
+fn continue_function<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>) -> FunctionValue<'a> {
+    if let Some(function) = module.get_function("cody.continue") {
+        return function;
+    }
+
+    let function: FunctionValue<'a> = declare_function(context, module, "cody.continue", 2);
+    let return_block = builder.get_insert_block().expect("Builder is not positioned in a block.");
+    let entry_block = context.append_basic_block(function, "entry");
+    builder.position_at_end(entry_block);
+
+    let resume: FunctionValue<'a> = value::runtime_function(module, "cody_continue", context.void_type().fn_type(&[word_type(context).into(), word_type(context).into()], false));
+    resume.add_attribute(AttributeLoc::Function, context.create_enum_attribute(Attribute::get_named_enum_kind_id("noreturn"), 0));
+    let arguments: Vec<BasicMetadataValueEnum<'a>> = function.get_param_iter().map(|param| param.into()).collect();
+    builder.build_call(resume, &arguments, "").expect("Failed to build call.");
+    builder.build_unreachable().expect("Failed to build unreachable.");
+
+    builder.position_at_end(return_block);
+    function
+}
+
+/// Calls a function of the runtime returning a word.
+fn build_runtime_call<'a>(builder: &Builder<'a>, function: FunctionValue<'a>, arguments: &[BasicMetadataValueEnum<'a>], name: &str) -> IntValue<'a> {
+    builder.build_call(function, arguments, name)
+        .expect("Failed to build call.")
+        .try_as_basic_value().left()
+        .expect("Call did not produce a value.")
+        .into_int_value()
+}
+
+/// Returns a value from the current function, popping its frame.
+fn build_return<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, value: IntValue<'a>) {
+    value::leave_frame(context, module, builder);
+    builder.build_return(Some(&value)).expect("Failed to build return.");
+}
+
+/// Branches on the value of a predicate, returning the blocks taken when it is true and when it is false.
+fn build_condition<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, scope: &Scope<'_, 'a>, pred: ExpressionAST) -> Result<(BasicBlock<'a>, BasicBlock<'a>), CodyError> {
+    let pred_value: IntValue<'a> = pred.codegen(context, module, builder, scope)?;
+    let pred_bool_value: IntValue<'a> = value::to_bool(context, builder, pred_value);
+    let function: FunctionValue<'a> = builder.get_insert_block().unwrap().get_parent().unwrap();
+    let then_block: BasicBlock<'a> = context.append_basic_block(function, "then");
+    let else_block: BasicBlock<'a> = context.append_basic_block(function, "else");
+    builder.build_conditional_branch(pred_bool_value, then_block, else_block).expect("Failed to build branch.");
+    Ok((then_block, else_block))
+}
+
+/// An arm of a match expression, whose code starts at `block`.
+struct MatchArm<'a> {
+    block: BasicBlock<'a>,
+    binding: Option<String>,
+    /// The names bound to the fields of the variant the arm matches.
+    fields: Vec<String>,
+    body: ExpressionAST,
+}
+
+/// Switches on the value of a match expression, returning the value and the arms it can reach.
+/// Arms are tried in order: integer patterns become switch cases, and the first arm with an
+/// identifier pattern catches every other value, binding it under that identifier. A match
+/// with variant patterns switches on the number of the variant of the value instead.
+fn build_match_dispatch<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, scope: &Scope<'_, 'a>, expr: ExpressionAST, arms: Vec<ExpressionAST>, span: Span) -> Result<(IntValue<'a>, Vec<MatchArm<'a>>), CodyError> {
+    let match_value: IntValue<'a> = expr.codegen(context, module, builder, scope)?;
+    let destructuring: bool = arms.iter().any(|arm| matches!(
+        arm,
+        ExpressionAST::MatchArmExpr(patterns, _) if patterns.iter().any(|pattern| matches!(pattern, ExpressionAST::VariantExpr(..)))
+    ));
+    let switch_value: IntValue<'a> = if destructuring {
+        enums::build_number(context, module, builder, match_value)
+    } else {
+        match_value
+    };
+    let match_block: BasicBlock<'a> = builder.get_insert_block().unwrap();
+    let function: FunctionValue<'a> = builder.get_insert_block().unwrap().get_parent().unwrap();
+
+    let mut cases: Vec<(IntValue<'a>, BasicBlock<'a>)> = Vec::new();
+    let mut seen_patterns: HashSet<u64> = HashSet::new();
+    let mut match_arms: Vec<MatchArm<'a>> = Vec::new();
+    let mut catch_all: Option<BasicBlock<'a>> = None;
+    for arm in arms {
+        let (patterns, body) = match arm {
+            ExpressionAST::MatchArmExpr(patterns, body) => (patterns, *body),
+            _ => panic!("Expected match arm in match expression.")
+        };
+        let arm_block: BasicBlock<'a> = context.append_basic_block(function, "arm");
+        let mut binding: Option<String> = None;
+        let mut fields: Vec<String> = Vec::new();
+        for pattern in patterns {
+            let case: IntValue<'a> = match pattern {
+                ExpressionAST::VariantExpr(variant, field_names) => {
+                    let (name, variant_span) = match *variant {
+                        ExpressionAST::VariableExpr(s, span) => (s, span),
+                        _ => panic!("Expected variable name in variant pattern.")
+                    };
+                    let variant: Variant = scope.get_variant(&name)
+                        .ok_or_else(|| CodyError::new(ErrorCode::UnboundVariable, format!("Variant {} not found in scope.", name), variant_span))?;
+                    if variant.arity != field_names.len() {
+                        return Err(CodyError::new(
+                            ErrorCode::ArityMismatch,
+                            format!("{} has {} fields, but its pattern binds {}.", name, variant.arity, field_names.len()),
+                            variant_span
+                        ));
+                    }
+                    fields = field_names.into_iter()
+                        .map(|field| match field {
+                            ExpressionAST::VariableExpr(s, _) => s,
+                            _ => panic!("Expected variable name in variant pattern.")
+                        })
+                        .collect();
+                    value::integer(context, variant.number as i64)
+                },
+                // the other patterns are values, which cannot be compared with variant numbers
+                ExpressionAST::IntegerExpr(_) | ExpressionAST::BoolExpr(_) | ExpressionAST::CharExpr(_) if destructuring => {
+                    return Err(CodyError::new(ErrorCode::TypeMismatch, "A match on variants can only have variant and identifier patterns.", span));
+                },
+                ExpressionAST::IntegerExpr(i) => value::integer(context, i as i64),
+                ExpressionAST::BoolExpr(b) => value::boolean(context, b),
+                ExpressionAST::CharExpr(c) => value::character(context, c),
+                // a variant without fields is matched by (variant), where a bare name would bind
+                // every value under the name of the variant
+                ExpressionAST::VariableExpr(s, span) if scope.get_variant(&s).is_some() => {
+                    return Err(CodyError::new(
+                        ErrorCode::InvalidPattern,
+                        format!("{} is a variant, which is matched by the pattern ({}).", s, s),
+                        span
+                    ));
+                },
+                ExpressionAST::VariableExpr(s, _) => {
+                    catch_all = Some(arm_block);
+                    binding = Some(s);
+                    continue;
+                },
+                _ => panic!("Unexpected pattern in match arm: {:?}", pattern)
+            };
+            // a value already matched by an earlier arm never reaches this one
+            if seen_patterns.insert(case.get_zero_extended_constant().unwrap()) {
+                cases.push((case, arm_block));
+            }
+        }
+        match_arms.push(MatchArm { block: arm_block, binding, fields, body });
+        // arms after a catch-all are unreachable
+        if catch_all.is_some() {
+            break;
+        }
+    }
+
+    // a match without a catch-all aborts the program on values none of its arms match, which
+    // static type checking rules out for enums
+    let default_block: BasicBlock<'a> = catch_all.unwrap_or_else(|| {
+        let no_match_block: BasicBlock<'a> = context.append_basic_block(function, "nomatch");
+        builder.position_at_end(no_match_block);
+        value::build_error(context, module, builder, "no arm of match matches the value");
+        builder.build_unreachable().expect("Failed to build unreachable.");
+        no_match_block
+    });
+    builder.position_at_end(match_block);
+    builder.build_switch(switch_value, default_block, &cases).expect("Failed to build switch.");
+    Ok((match_value, match_arms))
+}
+
+/// Returns the scope of a match arm, binding the matched value if the arm has an identifier pattern,
+/// and the fields of the matched variant if it has a variant pattern.
+fn match_arm_scope<'s, 'a>(context: &'a Context, builder: &Builder<'a>, scope: &'s Scope<'s, 'a>, binding: Option<String>, fields: Vec<String>, match_value: IntValue<'a>) -> Scope<'s, 'a> {
+    let arm_scope = Scope::new(Some(scope), scope.function);
+    if let Some(name) = binding {
+        let binding_pointer: PointerValue<'a> = value::build_slot(context, builder, name.as_str());
+        value::store_slot(builder, binding_pointer, match_value);
+        arm_scope.add_variable(name, binding_pointer);
+    }
+    for (i, name) in fields.into_iter().enumerate() {
+        let field_value: IntValue<'a> = enums::load_field(context, builder, match_value, i, name.as_str());
+        let field_pointer: PointerValue<'a> = value::build_slot(context, builder, name.as_str());
+        value::store_slot(builder, field_pointer, field_value);
+        arm_scope.add_variable(name, field_pointer);
+    }
+    arm_scope
+}
+
+/// The type of the code of a cody function: it takes its closure and `arity - 1` arguments,
+/// and returns a word.
+fn function_type(context: &Context, arity: usize) -> FunctionType<'_> {
+    let param_types: Vec<BasicMetadataTypeEnum> = vec![word_type(context).into(); arity];
+    word_type(context).fn_type(&param_types, false)
+}
+
+/// Adds the code of a function taking `arity - 1` arguments to the module.
+fn declare_function<'a>(context: &'a Context, module: &Module<'a>, name: &str, arity: usize) -> FunctionValue<'a> {
+    let function: FunctionValue<'a> = module.add_function(name, function_type(context, arity), Some(Linkage::Internal));
+    function.set_call_conventions(call_convention(module));
+    function
+}
+
+/// Generates a list of expressions in order, returning their values.
+/// Each value is held in a GC root while the expressions after it are generated, as they may allocate.
+fn codegen_all<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, scope: &Scope<'_, 'a>, expressions: Vec<ExpressionAST>) -> Result<Vec<IntValue<'a>>, CodyError> {
+    let count = expressions.len();
+    let mut slots: Vec<PointerValue<'a>> = Vec::new();
+    let mut last: Option<IntValue<'a>> = None;
+    for (i, expression) in expressions.into_iter().enumerate() {
+        let expression_value: IntValue<'a> = expression.codegen(context, module, builder, scope)?;
+        // nothing is generated after the last value before it is used
+        if i + 1 == count {
+            last = Some(expression_value);
+        } else {
+            slots.push(value::spill(context, builder, expression_value));
+        }
+    }
+    let mut values: Vec<IntValue<'a>> = slots.into_iter()
+        .map(|slot| value::load_slot(context, builder, slot, "spilled"))
+        .collect();
+    values.extend(last);
+    Ok(values)
+}
+
+/// Builds a closure, returning its code and the closure value.
+/// A closure is a record whose first word is the address of its code, followed by the number of
+/// arguments the code takes, so that calls can check it, and by its captured variables. The code takes the closure as its first argument, and binds the captured variables
+/// and parameters in a child scope of `scope`. Named closures also bind themselves under their name.
+fn build_closure<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, scope: &Scope<'_, 'a>, name: Option<&str>, closure: ExpressionAST) -> Result<(FunctionValue<'a>, IntValue<'a>), CodyError> {
+    let (captures, params, body) = match closure {
+        ExpressionAST::ClosureExpr(captures, params, body) => (captures, params, *body),
+        _ => panic!("Expected closure expression.")
+    };
+    let capture_names: Vec<(String, Span)> = captures.into_iter()
+        .map(|capture| match capture {
+            ExpressionAST::VariableExpr(s, span) => (s, span),
+            _ => panic!("Expected variable name in closure captures.")
+        })
+        .collect();
+    let function: FunctionValue<'a> = declare_function(context, module, name.unwrap_or("lambda"), params.len() + 1);
+
+    // closures without captured variables do not need to be allocated
+    let closure_value: IntValue<'a> = if capture_names.is_empty() {
+        static_closure(context, module, function)
+    } else {
+        let record: PointerValue<'a> = value::allocate(context, module, builder, capture_names.len() + 2, "closure");
+        value::store_field(context, builder, record, 0, function_address(context, builder, function));
+        value::store_field(context, builder, record, 1, value::integer(context, params.len() as i64));
+        for (i, (capture, span)) in capture_names.iter().enumerate() {
+            let capture_value: IntValue<'a> = ExpressionAST::VariableExpr(capture.clone(), *span).codegen(context, module, builder, scope)?;
+            value::store_field(context, builder, record, i + 2, capture_value);
+        }
+        value::tag_pointer(context, builder, record, CLOSURE_TAG)
+    };
+
+    let return_block = builder.get_insert_block().expect("Builder is not positioned in a block.");
+    let entry_block = context.append_basic_block(function, "entry");
+    builder.position_at_end(entry_block);
+    value::enter_frame(context, module, builder);
+
+    let function_scope = Scope::new(Some(scope), function);
+    let self_value: IntValue<'a> = function.get_nth_param(0).unwrap().into_int_value();
+    if let Some(name) = name {
+        let self_pointer: PointerValue<'a> = value::build_slot(context, builder, name);
+        value::store_slot(builder, self_pointer, self_value);
+        function_scope.add_function(name.to_string(), self_pointer, function);
+    }
+    if !capture_names.is_empty() {
+        let record: PointerValue<'a> = value::untag_pointer(context, builder, self_value);
+        for (i, (capture, _)) in capture_names.into_iter().enumerate() {
+            let capture_value: IntValue<'a> = value::load_field(context, builder, record, i + 2, capture.as_str());
+            let capture_pointer: PointerValue<'a> = value::build_slot(context, builder, capture.as_str());
+            value::store_slot(builder, capture_pointer, capture_value);
+            function_scope.add_variable(capture, capture_pointer);
+        }
+    }
+    for (param, argument) in params.into_iter().zip(function.get_param_iter().skip(1)) {
+        let param_name = match param {
+            ExpressionAST::VariableExpr(s, _) => s,
+            _ => panic!("Expected variable name in function parameters.")
+        };
+        let param_value: PointerValue<'a> = value::build_slot(context, builder, param_name.as_str());
+        value::store_slot(builder, param_value, argument.into_int_value());
+        function_scope.add_variable(param_name, param_value);
+    }
+
+    body.codegen_tail(context, module, builder, &function_scope)?;
+
+    builder.position_at_end(return_block);
+    Ok((function, closure_value))
+}
+
+/// Returns a closure without captured variables as a constant record in the module.
+fn static_closure<'a>(context: &'a Context, module: &Module<'a>, function: FunctionValue<'a>) -> IntValue<'a> {
+    let record_name = format!("{}.closure", function.get_name().to_str().expect("Function name is not valid UTF-8."));
+    let record = module.get_global(&record_name).unwrap_or_else(|| {
+        let code: IntValue<'a> = function.as_global_value().as_pointer_value().const_to_int(word_type(context));
+        let arity: IntValue<'a> = value::integer(context, function.count_params() as i64 - 1);
+        let record = module.add_global(word_type(context).array_type(2), None, &record_name);
+        record.set_initializer(&word_type(context).const_array(&[code, arity]));
+        record.set_constant(true);
+        record.set_linkage(Linkage::Private);
+        // the tag is kept in the low bits of the record's address
+        record.set_alignment(8);
+        record
+    });
+    record.as_pointer_value()
+        .const_to_int(word_type(context))
+        .const_add(word_type(context).const_int(CLOSURE_TAG, false))
+}
+
+/// Builds an atomic binary operator, aborting the program with a type error if an operand is
+/// not of the type the operator works on.
+/// Integers keep their tag bits clear, so most operators can work on them directly, while
+/// operators on floats work on the values loaded from their records.
+fn build_atomic_binary<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, op: AtomBinary, left: IntValue<'a>, right: IntValue<'a>) -> IntValue<'a> {
+    if op.is_float() {
+        return build_float_binary(context, module, builder, op, left, right);
+    }
+    // any two values can be compared for equality
+    if op != AtomBinary::Eq {
+        value::check_tag(context, module, builder, left, value::INTEGER_TAG, op.symbol(), "an integer");
+        value::check_tag(context, module, builder, right, value::INTEGER_TAG, op.symbol(), "an integer");
+    }
+    match op {
+        AtomBinary::Add => builder.build_int_add(left, right, "add").expect("Failed to build binary expression."),
+        AtomBinary::Sub => builder.build_int_sub(left, right, "sub").expect("Failed to build binary expression."),
+        AtomBinary::Mul => {
+            let left: IntValue<'a> = value::untag_integer(context, builder, left);
+            builder.build_int_mul(left, right, "mul").expect("Failed to build binary expression.")
+        },
+        AtomBinary::Div => {
+            let nonzero: IntValue<'a> = builder.build_int_compare(inkwell::IntPredicate::NE, right, value::integer(context, 0), "nonzero")
+                .expect("Failed to compare divisor.");
+            value::build_error_check(context, module, builder, nonzero, "division by zero");
+            let quotient: IntValue<'a> = builder.build_int_signed_div(left, right, "div").expect("Failed to build binary expression.");
+            value::tag_integer(context, builder, quotient)
+        },
+        AtomBinary::And => builder.build_and(left, right, "and").expect("Failed to build binary expression."),
+        AtomBinary::Or => builder.build_or(left, right, "or").expect("Failed to build binary expression."),
+        // only the value bits are flipped, so that the result is still an integer
+        AtomBinary::Not => builder.build_xor(left, word_type(context).const_int(!value::TAG_MASK, false), "not").expect("Failed to build binary expression."),
+        AtomBinary::Eq => {
+            let eq: IntValue<'a> = builder.build_int_compare(inkwell::IntPredicate::EQ, left, right, "eq").expect("Failed to build binary expression.");
+            value::from_bool(context, builder, eq)
+        },
+        AtomBinary::Lt => {
+            let lt: IntValue<'a> = builder.build_int_compare(inkwell::IntPredicate::SLT, left, right, "lt").expect("Failed to build binary expression.");
+            value::from_bool(context, builder, lt)
+        },
+        _ => unreachable!("Operators on floats are built by build_float_binary."),
+    }
+}
+
+fn build_float_binary<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, op: AtomBinary, left: IntValue<'a>, right: IntValue<'a>) -> IntValue<'a> {
+    value::check_tag(context, module, builder, left, value::FLOAT_TAG, op.symbol(), "a float");
+    value::check_tag(context, module, builder, right, value::FLOAT_TAG, op.symbol(), "a float");
+    let left: FloatValue<'a> = value::unbox_float(context, builder, left);
+    let right: FloatValue<'a> = value::unbox_float(context, builder, right);
+    let result: FloatValue<'a> = match op {
+        AtomBinary::FAdd => builder.build_float_add(left, right, "fadd").expect("Failed to build binary expression."),
+        AtomBinary::FSub => builder.build_float_sub(left, right, "fsub").expect("Failed to build binary expression."),
+        AtomBinary::FMul => builder.build_float_mul(left, right, "fmul").expect("Failed to build binary expression."),
+        AtomBinary::FDiv => builder.build_float_div(left, right, "fdiv").expect("Failed to build binary expression."),
+        AtomBinary::FEq => {
+            let eq: IntValue<'a> = builder.build_float_compare(inkwell::FloatPredicate::OEQ, left, right, "feq").expect("Failed to build binary expression.");
+            return value::from_bool(context, builder, eq);
+        },
+        AtomBinary::FLt => {
+            let lt: IntValue<'a> = builder.build_float_compare(inkwell::FloatPredicate::OLT, left, right, "flt").expect("Failed to build binary expression.");
+            return value::from_bool(context, builder, lt);
+        },
+        _ => unreachable!("Operators on integers are built by build_atomic_binary."),
+    };
+    value::box_float(context, module, builder, result)
+}
+
+fn operator_function_name(op: &AtomBinary) -> &'static str {
+    match op {
+        AtomBinary::Add => "cody.add",
+        AtomBinary::Sub => "cody.sub",
+        AtomBinary::Mul => "cody.mul",
+        AtomBinary::Div => "cody.div",
+        AtomBinary::And => "cody.and",
+        AtomBinary::Or => "cody.or",
+        AtomBinary::Not => "cody.not",
+        AtomBinary::Eq => "cody.eq",
+        AtomBinary::Lt => "cody.lt",
+        AtomBinary::FAdd => "cody.fadd",
+        AtomBinary::FSub => "cody.fsub",
+        AtomBinary::FMul => "cody.fmul",
+        AtomBinary::FDiv => "cody.fdiv",
+        AtomBinary::FEq => "cody.feq",
+        AtomBinary::FLt => "cody.flt",
+    }
+}
+
+/// Returns the primitive a call refers to, if its function is a primitive's name that no variable shadows.
+fn primitive_call(function: &ExpressionAST, scope: &Scope<'_, '_>) -> Option<Primitive> {
+    match function {
+        ExpressionAST::VariableExpr(s, _) if scope.get_variable(s).is_none() && scope.get_variant(s).is_none() => Primitive::from_name(s),
+        _ => None
+    }
+}
+
+/// Returns the variant a call builds a value of, if its function is the constructor of one.
+fn variant_call(function: &ExpressionAST, scope: &Scope<'_, '_>) -> Option<Variant> {
+    match function {
+        ExpressionAST::VariableExpr(s, _) => scope.get_variant(s),
+        _ => None
+    }
+}
+
+/// Returns the external function a call refers to, if its function is the name of one.
+fn extern_call<'a>(function: &ExpressionAST, scope: &Scope<'_, 'a>) -> Option<ForeignFunction<'a>> {
+    match function {
+        ExpressionAST::VariableExpr(s, _) => scope.get_extern(s),
+        _ => None
+    }
+}
+
+/// Returns an external function as a closure value.
+fn foreign_closure<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, name: &str, foreign_function: &ForeignFunction<'a>) -> IntValue<'a> {
+    let function: FunctionValue<'a> = primitive_function(context, module, builder, &format!("cody.extern.{}", name), foreign_function.parameter_types.len(), |arguments| {
+        foreign::build_call(context, module, builder, foreign_function, arguments)
+    });
+    static_closure(context, module, function)
+}
+
+/// Returns the function wrapping a primitive operation taking `arity` arguments, so that it can
+/// be used as a value. The function is generated the first time the primitive is used in the module.
+fn primitive_function<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, name: &str, arity: usize, build: impl Fn(&[IntValue<'a>]) -> IntValue<'a>) -> FunctionValue<'a> {
+    if let Some(function) = module.get_function(name) {
+        return function;
+    }
+
+    let function: FunctionValue<'a> = declare_function(context, module, name, arity + 1);
+    let return_block = builder.get_insert_block().expect("Builder is not positioned in a block.");
+    let entry_block = context.append_basic_block(function, "entry");
+    builder.position_at_end(entry_block);
+    value::enter_frame(context, module, builder);
+
+    let arguments: Vec<IntValue<'a>> = function.get_param_iter()
+        .skip(1)
+        .map(|param| param.into_int_value())
+        .collect();
+    let result: IntValue<'a> = build(&arguments);
+    build_return(context, module, builder, result);
+
+    builder.position_at_end(return_block);
+    function
+}
+
+/// Returns the address of a function's code as a word.
+fn function_address<'a>(context: &'a Context, builder: &Builder<'a>, function: FunctionValue<'a>) -> IntValue<'a> {
+    builder.build_ptr_to_int(function.as_global_value().as_pointer_value(), word_type(context), "fnaddr")
+        .expect("Failed to take function address.")
+}
@@ -0,0 +1,382 @@
+//! Bit-precise integers, such as `u8` or `i3`.
+//! A bit-precise integer is a record of two words: the negated code of its type, as an integer,
+//! followed by its value extended to 64 bits, with its sign if the type is signed and with zeros
+//! otherwise.
+//!
+//! The type of a bit-precise integer is only known at run time, as functions can work on
+//! integers of any width. Arithmetic is done on words, with the operands shifted to the top bits
+//! of the word: there, an operation on `w` bits overflows exactly when the same operation on
+//! 64 bits does, and its result is shifted back down.
+
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::intrinsics::Intrinsic;
+use inkwell::module::{Linkage, Module};
+use inkwell::values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, IntValue, PhiValue, PointerValue, StructValue};
+use inkwell::IntPredicate;
+
+use crate::parser::token_types::BitType;
+
+use super::value::{self, word_type, INTEGER_TAG, RECORD_TAG};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arithmetic {
+    Add,
+    Sub,
+    Mul,
+}
+
+/// What arithmetic does with a result that does not fit in the type of its operands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// Keeps the low bits of the result.
+    Wrapping,
+    /// Aborts the program.
+    Checked,
+    /// Clamps the result to the smallest or largest value of the type.
+    Saturating,
+}
+
+impl Arithmetic {
+    /// The name of the operation with the given overflow, as it is written in a program.
+    pub fn name(&self, overflow: Overflow) -> &'static str {
+        match (overflow, self) {
+            (Overflow::Wrapping, Arithmetic::Add) => "wrapping-add",
+            (Overflow::Wrapping, Arithmetic::Sub) => "wrapping-sub",
+            (Overflow::Wrapping, Arithmetic::Mul) => "wrapping-mul",
+            (Overflow::Checked, Arithmetic::Add) => "checked-add",
+            (Overflow::Checked, Arithmetic::Sub) => "checked-sub",
+            (Overflow::Checked, Arithmetic::Mul) => "checked-mul",
+            (Overflow::Saturating, Arithmetic::Add) => "saturating-add",
+            (Overflow::Saturating, Arithmetic::Sub) => "saturating-sub",
+            (Overflow::Saturating, Arithmetic::Mul) => "saturating-mul",
+        }
+    }
+}
+
+/// The first word of the records of the integers of a type.
+fn descriptor(context: &Context, bit_type: BitType) -> IntValue<'_> {
+    value::integer(context, -(bit_type.code() as i64))
+}
+
+/// A bit-precise integer constant, a constant record in the module.
+pub fn constant<'a>(context: &'a Context, module: &Module<'a>, bit_type: BitType, extended: u64) -> IntValue<'a> {
+    let bits: IntValue<'a> = word_type(context).const_int(extended, false);
+    let global = module.add_global(word_type(context).array_type(2), None, "cody.bits");
+    global.set_initializer(&word_type(context).const_array(&[descriptor(context, bit_type), bits]));
+    global.set_constant(true);
+    global.set_linkage(Linkage::Private);
+    global.set_alignment(8);
+    global.as_pointer_value()
+        .const_to_int(word_type(context))
+        .const_add(word_type(context).const_int(RECORD_TAG, false))
+}
+
+/// Allocates a record holding a bit-precise integer on the heap.
+fn build_box<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, descriptor: IntValue<'a>, extended: IntValue<'a>) -> IntValue<'a> {
+    let record: PointerValue<'a> = value::allocate_raw(context, module, builder, 16, "bits");
+    value::store_field(context, builder, record, 0, descriptor);
+    value::store_field(context, builder, record, 1, extended);
+    value::tag_pointer(context, builder, record, RECORD_TAG)
+}
+
+/// A bit-precise integer loaded from its record.
+struct Unboxed<'a> {
+    descriptor: IntValue<'a>,
+    width: IntValue<'a>,
+    /// Whether the type is signed, an `i1`.
+    signed: IntValue<'a>,
+    extended: IntValue<'a>,
+}
+
+/// Loads a bit-precise integer, aborting the program with a type error if the value is not one.
+fn unbox<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, word: IntValue<'a>, operation: &str) -> Unboxed<'a> {
+    value::check_tag(context, module, builder, word, RECORD_TAG, operation, "a bit-precise integer");
+    let record: PointerValue<'a> = value::untag_pointer(context, builder, word);
+    // the records of the values of enums start with the number of their variant instead
+    let descriptor: IntValue<'a> = value::load_field(context, builder, record, 0, "descriptor");
+    let is_bits: IntValue<'a> = builder.build_int_compare(IntPredicate::SLT, descriptor, word_type(context).const_zero(), "isbits")
+        .expect("Failed to compare descriptor.");
+    value::build_type_check(context, module, builder, is_bits, word, operation, "a bit-precise integer");
+
+    let code: IntValue<'a> = builder.build_int_neg(value::untag_integer(context, builder, descriptor), "code")
+        .expect("Failed to read type.");
+    let width: IntValue<'a> = builder.build_right_shift(code, word_type(context).const_int(1, false), false, "width")
+        .expect("Failed to read width.");
+    let signed: IntValue<'a> = builder.build_int_truncate(code, context.bool_type(), "signed")
+        .expect("Failed to read signedness.");
+    let extended: IntValue<'a> = value::load_field(context, builder, record, 1, "bits");
+    Unboxed { descriptor, width, signed, extended }
+}
+
+/// Loads the operands of a binary operation, which must have the same type.
+fn unbox_operands<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, left: IntValue<'a>, right: IntValue<'a>, operation: &str) -> (Unboxed<'a>, Unboxed<'a>) {
+    let left: Unboxed<'a> = unbox(context, module, builder, left, operation);
+    let right_word: IntValue<'a> = right;
+    let right: Unboxed<'a> = unbox(context, module, builder, right, operation);
+    let same_type: IntValue<'a> = builder.build_int_compare(IntPredicate::EQ, left.descriptor, right.descriptor, "sametype")
+        .expect("Failed to compare types.");
+    value::build_type_check(context, module, builder, same_type, right_word, operation, "a bit-precise integer of the same type");
+    (left, right)
+}
+
+/// The number of bits above the integer in its word.
+fn top_shift<'a>(context: &'a Context, builder: &Builder<'a>, width: IntValue<'a>) -> IntValue<'a> {
+    builder.build_int_sub(word_type(context).const_int(64, false), width, "shift")
+        .expect("Failed to compute shift.")
+}
+
+/// Shifts an extended integer to the top bits of its word.
+fn to_top<'a>(builder: &Builder<'a>, extended: IntValue<'a>, shift: IntValue<'a>) -> IntValue<'a> {
+    builder.build_left_shift(extended, shift, "top").expect("Failed to shift integer.")
+}
+
+/// Shifts an integer in the top bits of its word back down, extending it.
+fn from_top<'a>(builder: &Builder<'a>, top: IntValue<'a>, shift: IntValue<'a>, signed: IntValue<'a>) -> IntValue<'a> {
+    let with_sign: IntValue<'a> = builder.build_right_shift(top, shift, true, "extended").expect("Failed to shift integer.");
+    let with_zeros: IntValue<'a> = builder.build_right_shift(top, shift, false, "extended").expect("Failed to shift integer.");
+    select(builder, signed, with_sign, with_zeros)
+}
+
+fn select<'a>(builder: &Builder<'a>, condition: IntValue<'a>, then: IntValue<'a>, otherwise: IntValue<'a>) -> IntValue<'a> {
+    builder.build_select(condition, then, otherwise, "select")
+        .expect("Failed to build select.")
+        .into_int_value()
+}
+
+/// Calls an intrinsic overloaded on the type of words.
+fn build_intrinsic<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, name: &str, arguments: &[BasicMetadataValueEnum<'a>]) -> BasicValueEnum<'a> {
+    let intrinsic: FunctionValue<'a> = Intrinsic::find(name)
+        .and_then(|intrinsic| intrinsic.get_declaration(module, &[word_type(context).into()]))
+        .unwrap_or_else(|| panic!("Failed to declare {}.", name));
+    builder.build_call(intrinsic, arguments, "intrinsic")
+        .expect("Failed to build call.")
+        .try_as_basic_value().left()
+        .expect("Intrinsic did not produce a value.")
+}
+
+/// Builds arithmetic on two bit-precise integers of the same type.
+pub fn build_arithmetic<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, arithmetic: Arithmetic, overflow: Overflow, left: IntValue<'a>, right: IntValue<'a>) -> IntValue<'a> {
+    let name: &str = arithmetic.name(overflow);
+    let (left, right) = unbox_operands(context, module, builder, left, right, name);
+    let shift: IntValue<'a> = top_shift(context, builder, left.width);
+    let top_left: IntValue<'a> = to_top(builder, left.extended, shift);
+    // the product of an integer in the top bits with another is in the top bits
+    let top_right: IntValue<'a> = match arithmetic {
+        Arithmetic::Mul => right.extended,
+        _ => to_top(builder, right.extended, shift),
+    };
+
+    let top_result: IntValue<'a> = match overflow {
+        Overflow::Wrapping => match arithmetic {
+            Arithmetic::Add => builder.build_int_add(top_left, top_right, "sum"),
+            Arithmetic::Sub => builder.build_int_sub(top_left, top_right, "difference"),
+            Arithmetic::Mul => builder.build_int_mul(top_left, top_right, "product"),
+        }.expect("Failed to build arithmetic."),
+        Overflow::Checked => {
+            let operation = match arithmetic {
+                Arithmetic::Add => "add",
+                Arithmetic::Sub => "sub",
+                Arithmetic::Mul => "mul",
+            };
+            let arguments: [BasicMetadataValueEnum<'a>; 2] = [top_left.into(), top_right.into()];
+            let signed: StructValue<'a> = build_intrinsic(context, module, builder, &format!("llvm.s{}.with.overflow", operation), &arguments).into_struct_value();
+            let unsigned: StructValue<'a> = build_intrinsic(context, module, builder, &format!("llvm.u{}.with.overflow", operation), &arguments).into_struct_value();
+            let field = |result: StructValue<'a>, index: u32| builder.build_extract_value(result, index, "result")
+                .expect("Failed to read result.")
+                .into_int_value();
+            let overflowed: IntValue<'a> = select(builder, left.signed, field(signed, 1), field(unsigned, 1));
+            let fits: IntValue<'a> = builder.build_not(overflowed, "fits").expect("Failed to build not.");
+            value::build_error_check(context, module, builder, fits, &format!("overflow in {}", name));
+            select(builder, left.signed, field(signed, 0), field(unsigned, 0))
+        },
+        Overflow::Saturating => {
+            let (signed, unsigned) = match arithmetic {
+                Arithmetic::Add => ("llvm.sadd.sat", "llvm.uadd.sat"),
+                Arithmetic::Sub => ("llvm.ssub.sat", "llvm.usub.sat"),
+                Arithmetic::Mul => ("llvm.smul.fix.sat", "llvm.umul.fix.sat"),
+            };
+            let mut arguments: Vec<BasicMetadataValueEnum<'a>> = vec![top_left.into(), top_right.into()];
+            // multiplication of fixed point numbers, without fractional bits
+            if arithmetic == Arithmetic::Mul {
+                arguments.push(context.i32_type().const_zero().into());
+            }
+            let signed: IntValue<'a> = build_intrinsic(context, module, builder, signed, &arguments).into_int_value();
+            let unsigned: IntValue<'a> = build_intrinsic(context, module, builder, unsigned, &arguments).into_int_value();
+            select(builder, left.signed, signed, unsigned)
+        },
+    };
+    let extended: IntValue<'a> = from_top(builder, top_result, shift, left.signed);
+    build_box(context, module, builder, left.descriptor, extended)
+}
+
+/// Builds the quotient or the remainder of two bit-precise integers of the same type, rounding
+/// towards zero. Division by zero, and the quotient of the smallest signed integer by -1, which
+/// does not fit in its type, abort the program.
+pub fn build_division<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, remainder: bool, left: IntValue<'a>, right: IntValue<'a>) -> IntValue<'a> {
+    let name: &str = if remainder { "bits-rem" } else { "bits-div" };
+    let (left, right) = unbox_operands(context, module, builder, left, right, name);
+    let zero: IntValue<'a> = word_type(context).const_zero();
+    let nonzero: IntValue<'a> = builder.build_int_compare(IntPredicate::NE, right.extended, zero, "nonzero")
+        .expect("Failed to compare divisor.");
+    value::build_error_check(context, module, builder, nonzero, "division by zero");
+
+    let minus_one: IntValue<'a> = word_type(context).const_all_ones();
+    let sign_bit: IntValue<'a> = builder.build_int_sub(left.width, word_type(context).const_int(1, false), "signbit")
+        .expect("Failed to compute sign bit.");
+    let smallest: IntValue<'a> = builder.build_left_shift(minus_one, sign_bit, "smallest")
+        .expect("Failed to compute smallest integer.");
+    let is_smallest: IntValue<'a> = builder.build_int_compare(IntPredicate::EQ, left.extended, smallest, "issmallest")
+        .expect("Failed to compare dividend.");
+    let is_minus_one: IntValue<'a> = builder.build_int_compare(IntPredicate::EQ, right.extended, minus_one, "isminusone")
+        .expect("Failed to compare divisor.");
+    let overflows: IntValue<'a> = builder.build_and(is_smallest, is_minus_one, "overflows").expect("Failed to build and.");
+    // the smallest signed integer divided by -1 is undefined on words, and its remainder is 0
+    let safe_divisor: IntValue<'a> = select(builder, overflows, word_type(context).const_int(1, false), right.extended);
+    if !remainder {
+        let signed_overflow: IntValue<'a> = builder.build_and(overflows, left.signed, "signedoverflow").expect("Failed to build and.");
+        let fits: IntValue<'a> = builder.build_not(signed_overflow, "fits").expect("Failed to build not.");
+        value::build_error_check(context, module, builder, fits, "overflow in bits-div");
+    }
+
+    let (signed, unsigned) = if remainder {
+        (builder.build_int_signed_rem(left.extended, safe_divisor, "remainder"), builder.build_int_unsigned_rem(left.extended, right.extended, "remainder"))
+    } else {
+        (builder.build_int_signed_div(left.extended, safe_divisor, "quotient"), builder.build_int_unsigned_div(left.extended, right.extended, "quotient"))
+    };
+    let signed: IntValue<'a> = signed.expect("Failed to build division.");
+    let unsigned: IntValue<'a> = unsigned.expect("Failed to build division.");
+    let extended: IntValue<'a> = select(builder, left.signed, signed, unsigned);
+    build_box(context, module, builder, left.descriptor, extended)
+}
+
+/// The bitwise operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bitwise {
+    And,
+    Or,
+    Xor,
+}
+
+/// Builds a bitwise operation on two bit-precise integers of the same type.
+pub fn build_bitwise<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, bitwise: Bitwise, left: IntValue<'a>, right: IntValue<'a>) -> IntValue<'a> {
+    let name: &str = match bitwise {
+        Bitwise::And => "bit-and",
+        Bitwise::Or => "bit-or",
+        Bitwise::Xor => "bit-xor",
+    };
+    let (left, right) = unbox_operands(context, module, builder, left, right, name);
+    // the bits above the integers are the same in both words, so they stay extended
+    let extended: IntValue<'a> = match bitwise {
+        Bitwise::And => builder.build_and(left.extended, right.extended, "and"),
+        Bitwise::Or => builder.build_or(left.extended, right.extended, "or"),
+        Bitwise::Xor => builder.build_xor(left.extended, right.extended, "xor"),
+    }.expect("Failed to build bitwise operation.");
+    build_box(context, module, builder, left.descriptor, extended)
+}
+
+/// Builds the complement of a bit-precise integer.
+pub fn build_not<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, word: IntValue<'a>) -> IntValue<'a> {
+    let integer: Unboxed<'a> = unbox(context, module, builder, word, "bit-not");
+    let shift: IntValue<'a> = top_shift(context, builder, integer.width);
+    let complement: IntValue<'a> = builder.build_not(to_top(builder, integer.extended, shift), "not").expect("Failed to build not.");
+    let extended: IntValue<'a> = from_top(builder, complement, shift, integer.signed);
+    build_box(context, module, builder, integer.descriptor, extended)
+}
+
+/// Builds a shift of a bit-precise integer by an integer number of bits, smaller than its width.
+/// Shifting left drops the bits shifted out, and shifting right keeps the sign of signed integers.
+pub fn build_shift<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, left: bool, word: IntValue<'a>, amount: IntValue<'a>) -> IntValue<'a> {
+    let name: &str = if left { "shift-left" } else { "shift-right" };
+    let integer: Unboxed<'a> = unbox(context, module, builder, word, name);
+    value::check_tag(context, module, builder, amount, INTEGER_TAG, name, "an integer");
+    let amount: IntValue<'a> = value::untag_integer(context, builder, amount);
+    // compared unsigned, negative amounts are too large
+    let in_range: IntValue<'a> = builder.build_int_compare(IntPredicate::ULT, amount, integer.width, "inrange")
+        .expect("Failed to compare shift amount.");
+    value::build_error_check(context, module, builder, in_range, &format!("shift amount out of range in {}", name));
+
+    let extended: IntValue<'a> = if left {
+        let shift: IntValue<'a> = top_shift(context, builder, integer.width);
+        let shifted: IntValue<'a> = builder.build_left_shift(to_top(builder, integer.extended, shift), amount, "shifted")
+            .expect("Failed to shift integer.");
+        from_top(builder, shifted, shift, integer.signed)
+    } else {
+        let with_sign: IntValue<'a> = builder.build_right_shift(integer.extended, amount, true, "shifted").expect("Failed to shift integer.");
+        let with_zeros: IntValue<'a> = builder.build_right_shift(integer.extended, amount, false, "shifted").expect("Failed to shift integer.");
+        select(builder, integer.signed, with_sign, with_zeros)
+    };
+    build_box(context, module, builder, integer.descriptor, extended)
+}
+
+/// Compares two bit-precise integers of the same type, for equality or for being less than the other.
+pub fn build_comparison<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, less: bool, left: IntValue<'a>, right: IntValue<'a>) -> IntValue<'a> {
+    let name: &str = if less { "bits-less?" } else { "bits-equal?" };
+    let (left, right) = unbox_operands(context, module, builder, left, right, name);
+    let compare = |predicate: IntPredicate| builder.build_int_compare(predicate, left.extended, right.extended, "compare")
+        .expect("Failed to compare integers.");
+    let result: IntValue<'a> = if less {
+        select(builder, left.signed, compare(IntPredicate::SLT), compare(IntPredicate::ULT))
+    } else {
+        compare(IntPredicate::EQ)
+    };
+    value::from_bool(context, builder, result)
+}
+
+/// Converts a bit-precise integer into an integer, aborting the program if it does not fit.
+pub fn build_to_integer<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, word: IntValue<'a>) -> IntValue<'a> {
+    let integer: Unboxed<'a> = unbox(context, module, builder, word, "integer");
+    let tagged: IntValue<'a> = value::tag_integer(context, builder, integer.extended);
+    let untagged: IntValue<'a> = value::untag_integer(context, builder, tagged);
+    let keeps_value: IntValue<'a> = builder.build_int_compare(IntPredicate::EQ, untagged, integer.extended, "keepsvalue")
+        .expect("Failed to compare integers.");
+    // unsigned integers from 2^63 look negative in their word
+    let negative: IntValue<'a> = builder.build_int_compare(IntPredicate::SLT, integer.extended, word_type(context).const_zero(), "negative")
+        .expect("Failed to compare integers.");
+    let unsigned: IntValue<'a> = builder.build_not(integer.signed, "unsigned").expect("Failed to build not.");
+    let too_large: IntValue<'a> = builder.build_and(unsigned, negative, "toolarge").expect("Failed to build and.");
+    let fits: IntValue<'a> = builder.build_and(keeps_value, builder.build_not(too_large, "small").expect("Failed to build not."), "fits")
+        .expect("Failed to build and.");
+    value::build_error_check(context, module, builder, fits, "overflow in integer");
+    tagged
+}
+
+/// Converts an integer or a bit-precise integer of any type into an integer of the given type,
+/// keeping its low bits.
+pub fn build_conversion<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, bit_type: BitType, word: IntValue<'a>) -> IntValue<'a> {
+    let function: FunctionValue<'a> = builder.get_insert_block().unwrap().get_parent().unwrap();
+    let integer_block = context.append_basic_block(function, "frominteger");
+    let bits_block = context.append_basic_block(function, "frombits");
+    let merge_block = context.append_basic_block(function, "convert");
+    let is_integer: IntValue<'a> = value::has_tag(context, builder, word, INTEGER_TAG);
+    builder.build_conditional_branch(is_integer, integer_block, bits_block).expect("Failed to build branch.");
+
+    builder.position_at_end(integer_block);
+    let from_integer: IntValue<'a> = value::untag_integer(context, builder, word);
+    builder.build_unconditional_branch(merge_block).expect("Failed to build branch.");
+
+    builder.position_at_end(bits_block);
+    let from_bits: IntValue<'a> = unbox(context, module, builder, word, &bit_type.to_string()).extended;
+    builder.build_unconditional_branch(merge_block).expect("Failed to build branch.");
+    let bits_block = builder.get_insert_block().unwrap();
+
+    builder.position_at_end(merge_block);
+    let phi_node: PhiValue<'a> = builder.build_phi(word_type(context), "converted").unwrap();
+    phi_node.add_incoming(&[(&from_integer, integer_block), (&from_bits, bits_block)]);
+    let extended: IntValue<'a> = phi_node.as_basic_value().into_int_value();
+
+    // the integer is cut down to the width of its type, then extended back to a word
+    let extended: IntValue<'a> = if bit_type.width < 64 {
+        let integer_type = context.custom_width_int_type(bit_type.width);
+        let truncated: IntValue<'a> = builder.build_int_truncate(extended, integer_type, "truncated")
+            .expect("Failed to truncate integer.");
+        if bit_type.signed {
+            builder.build_int_s_extend(truncated, word_type(context), "extended")
+        } else {
+            builder.build_int_z_extend(truncated, word_type(context), "extended")
+        }.expect("Failed to extend integer.")
+    } else {
+        extended
+    };
+    build_box(context, module, builder, descriptor(context, bit_type), extended)
+}
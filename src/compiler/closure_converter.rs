@@ -0,0 +1,208 @@
+//! Closure conversion.
+//! Rewrites every `FunctionExpr` into a `ClosureExpr` that lists the variables it captures
+//! from enclosing functions, so that codegen can copy them into a heap-allocated environment
+//! record instead of reaching into another function's stack.
+
+use std::collections::HashSet;
+
+use crate::error::Span;
+use crate::parser::node_types::ExpressionAST::{self, *};
+
+/// The variables visible in one function body during the conversion.
+struct Frame {
+    /// Variables bound by the function: its parameters and local definitions.
+    bound: HashSet<String>,
+    /// Variables bound by the patterns and definitions of the match arms being converted.
+    arm_bound: HashSet<String>,
+    /// Whether a match arm is being converted, whose definitions are local to it.
+    in_arm: bool,
+    /// Variables the function captures from enclosing functions, in order of first use.
+    captured: Vec<(String, Span)>,
+}
+
+impl Frame {
+    fn new(bound: HashSet<String>) -> Frame {
+        Frame {
+            bound,
+            arm_bound: HashSet::new(),
+            in_arm: false,
+            captured: Vec::new()
+        }
+    }
+}
+
+/// Closure converts a program.
+pub fn closure_convert(ast: ExpressionAST) -> ExpressionAST {
+    // top level definitions are globals, which functions refer to directly rather than capture.
+    // they are collected up front so that functions may refer to globals defined after them.
+    let mut globals: HashSet<String> = HashSet::new();
+    collect_globals(&ast, &mut globals);
+
+    let mut frames: Vec<Frame> = vec![Frame::new(globals)];
+    convert(ast, &mut frames)
+}
+
+/// Collects the names defined at the top level of a program, outside of any function.
+pub(crate) fn collect_globals(expression: &ExpressionAST, globals: &mut HashSet<String>) {
+    match expression {
+        DefineExpr(var, val) => {
+            if let VariableExpr(s, _) = var.as_ref() {
+                globals.insert(s.clone());
+            }
+            collect_globals(val, globals);
+        },
+        ExternExpr(var, _, _) => {
+            if let VariableExpr(s, _) = var.as_ref() {
+                globals.insert(s.clone());
+            }
+        },
+        SeqExpr(seq) => seq.iter().for_each(|expr| collect_globals(expr, globals)),
+        IfExpr(pred, conseq, alt, _) => {
+            collect_globals(pred, globals);
+            collect_globals(conseq, globals);
+            collect_globals(alt, globals);
+        },
+        CallExpr(function, arguments, _) => {
+            collect_globals(function, globals);
+            arguments.iter().for_each(|expr| collect_globals(expr, globals));
+        },
+        AtomBinExpr(_, l, r, _) | PairExpr(l, r) => {
+            collect_globals(l, globals);
+            collect_globals(r, globals);
+        },
+        // definitions inside functions are local to them
+        _ => ()
+    }
+}
+
+/// Records a use of `name` in the innermost frame, marking it as captured by every
+/// function between the use and the frame that binds it, as the enclosing functions
+/// need the variable too in order to build the inner environment.
+fn reference(name: &str, span: Span, frames: &mut [Frame]) {
+    let binding_frame = frames.iter()
+        .rposition(|frame| frame.bound.contains(name) || frame.arm_bound.contains(name));
+    match binding_frame {
+        // globals are never captured, but match bindings at the top level are not globals
+        Some(0) if !frames[0].arm_bound.contains(name) => (),
+        None => (),
+        Some(i) => {
+            for frame in frames[i + 1..].iter_mut() {
+                if !frame.captured.iter().any(|(captured, _)| captured == name) {
+                    frame.captured.push((name.to_string(), span));
+                }
+            }
+        }
+    }
+}
+
+fn convert(expression: ExpressionAST, frames: &mut Vec<Frame>) -> ExpressionAST {
+    match expression {
+        VariableExpr(s, span) => {
+            reference(&s, span, frames);
+            VariableExpr(s, span)
+        },
+        FunctionExpr(params, body) => convert_function(None, params, *body, frames),
+        DefineExpr(var, val) => {
+            let var_name = match var.as_ref() {
+                VariableExpr(s, _) => s.clone(),
+                _ => panic!("Expected variable name in define expression.")
+            };
+            let val = match *val {
+                // a defined function refers to itself through its own closure
+                FunctionExpr(params, body) => convert_function(Some(&var_name), params, *body, frames),
+                val => convert(val, frames),
+            };
+            // a definition in a match arm is only in scope in the arm, so even at the top level
+            // it is not a global, and functions capture it
+            let frame: &mut Frame = frames.last_mut().unwrap();
+            if frame.in_arm {
+                frame.arm_bound.insert(var_name);
+            } else {
+                frame.bound.insert(var_name);
+            }
+            DefineExpr(var, Box::new(val))
+        },
+        CallExpr(function, arguments, span) => {
+            let function = convert(*function, frames);
+            let arguments = arguments.into_iter().map(|argument| convert(argument, frames)).collect();
+            CallExpr(Box::new(function), arguments, span)
+        },
+        IfExpr(pred, conseq, alt, span) => IfExpr(
+            Box::new(convert(*pred, frames)),
+            Box::new(convert(*conseq, frames)),
+            Box::new(convert(*alt, frames)),
+            span
+        ),
+        MatchExpr(expression, arms, span) => {
+            let expression = convert(*expression, frames);
+            let arms = arms.into_iter().map(|arm| convert(arm, frames)).collect();
+            MatchExpr(Box::new(expression), arms, span)
+        },
+        MatchArmExpr(patterns, body) => {
+            // an identifier pattern binds the matched value for the arm's body only, as does a
+            // variant pattern for the fields of the variant
+            let bindings: Vec<String> = patterns.iter()
+                .flat_map(|pattern| match pattern {
+                    VariableExpr(s, _) => vec![s.clone()],
+                    VariantExpr(_, fields) => fields.iter()
+                        .map(|field| match field {
+                            VariableExpr(s, _) => s.clone(),
+                            _ => panic!("Expected variable name in variant pattern.")
+                        })
+                        .collect(),
+                    _ => Vec::new()
+                })
+                .collect();
+            let frame: &mut Frame = frames.last_mut().unwrap();
+            let outer_bound: HashSet<String> = frame.arm_bound.clone();
+            let outer_in_arm: bool = frame.in_arm;
+            frame.arm_bound.extend(bindings);
+            frame.in_arm = true;
+            let body = convert(*body, frames);
+            // the bindings and definitions of the arm go out of scope after it
+            let frame: &mut Frame = frames.last_mut().unwrap();
+            frame.arm_bound = outer_bound;
+            frame.in_arm = outer_in_arm;
+            MatchArmExpr(patterns, Box::new(body))
+        },
+        SeqExpr(seq) => SeqExpr(seq.into_iter().map(|expr| convert(expr, frames)).collect()),
+        AtomBinExpr(op, l, r, span) => AtomBinExpr(op, Box::new(convert(*l, frames)), Box::new(convert(*r, frames)), span),
+        PairExpr(l, r) => PairExpr(Box::new(convert(*l, frames)), Box::new(convert(*r, frames))),
+        ContExpr(function, span) => ContExpr(Box::new(convert(*function, frames)), span),
+        // external functions are bound like definitions, functions nested in the scope of a
+        // local declaration capture the external function as a value
+        ExternExpr(var, parameter_types, return_type) => {
+            if let VariableExpr(s, _) = var.as_ref() {
+                frames.last_mut().unwrap().bound.insert(s.clone());
+            }
+            ExternExpr(var, parameter_types, return_type)
+        },
+        // as are the constructors of the variants of an enum
+        EnumExpr(var, variants) => {
+            for variant in &variants {
+                frames.last_mut().unwrap().bound.insert(variant.name.clone());
+            }
+            EnumExpr(var, variants)
+        },
+        other => other
+    }
+}
+
+fn convert_function(self_name: Option<&str>, params: Vec<ExpressionAST>, body: ExpressionAST, frames: &mut Vec<Frame>) -> ExpressionAST {
+    let mut bound: HashSet<String> = params.iter()
+        .map(|param| match param {
+            VariableExpr(s, _) => s.clone(),
+            _ => panic!("Expected variable name in function parameters.")
+        })
+        .collect();
+    if let Some(name) = self_name {
+        bound.insert(name.to_string());
+    }
+
+    frames.push(Frame::new(bound));
+    let body = convert(body, frames);
+    let frame = frames.pop().unwrap();
+
+    let captures = frame.captured.into_iter().map(|(s, span)| VariableExpr(s, span)).collect();
+    ClosureExpr(captures, params, Box::new(body))
+}
@@ -0,0 +1,177 @@
+//! Writing compiled programs to files.
+//! Modules are optimised at the level asked for before they are written. Assembly and object
+//! files are generated by LLVM's target machine for the platform, the host unless another is
+//! given, and executables are linked from an object file and the runtime by a C compiler.
+
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use clap::ValueEnum;
+use inkwell::module::Module;
+use inkwell::targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple};
+use inkwell::OptimizationLevel;
+
+use crate::error::{CodyError, ErrorCode, Span};
+
+use super::optimize::{self, Optimization};
+
+/// The source of the runtime, compiled into every executable.
+const RUNTIME_SOURCE: &str = include_str!("../../runtime/cody_runtime.c");
+
+/// The kinds of file a program can be compiled into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Emit {
+    /// LLVM IR as text
+    LlvmIr,
+    /// LLVM bitcode
+    LlvmBc,
+    /// Assembly for the target
+    Asm,
+    /// An object file, to be linked with the runtime
+    Obj,
+    /// An executable, linked with the runtime
+    Exe,
+}
+
+/// The platform code is generated for. Each part left out is that of the host, or the generic
+/// CPU and its default features.
+#[derive(Clone, Debug, Default)]
+pub struct Platform {
+    /// The target triple, such as `aarch64-unknown-linux-gnu`.
+    pub triple: Option<String>,
+    pub cpu: Option<String>,
+    /// The features of the CPU, such as `+sse4.2,-avx`.
+    pub features: Option<String>,
+}
+
+/// Optimises a module for a platform at the given level, and writes it to the output file as the
+/// given kind of file.
+pub fn write(module: &Module<'_>, emit: Emit, output: &str, platform: &Platform, optimization: Optimization) -> Result<(), CodyError> {
+    let machine: TargetMachine = optimize::optimize(module, platform, optimization)?;
+    match emit {
+        Emit::LlvmIr => module.print_to_file(output)
+            .map_err(|error| write_failed(Path::new(output), error)),
+        Emit::LlvmBc => {
+            if module.write_bitcode_to_path(Path::new(output)) {
+                Ok(())
+            } else {
+                Err(write_failed(Path::new(output), "LLVM could not write the bitcode"))
+            }
+        },
+        Emit::Asm => write_code(&machine, module, FileType::Assembly, Path::new(output)),
+        Emit::Obj => write_code(&machine, module, FileType::Object, Path::new(output)),
+        Emit::Exe => {
+            let directory: PathBuf = env::temp_dir().join(format!("cody-{}", process::id()));
+            fs::create_dir_all(&directory).map_err(|error| write_failed(&directory, error))?;
+            let object: PathBuf = directory.join("program.o");
+            let linked = write_code(&machine, module, FileType::Object, &object)
+                .and_then(|()| link(&directory, &object, output, platform));
+            // the temporary files are removed even if linking failed
+            let _ = fs::remove_dir_all(&directory);
+            linked
+        },
+    }
+}
+
+/// The target machine generating code for a platform at the given level. Fails if LLVM does not
+/// know the target, or the CPU and features it is given.
+pub fn target_machine(platform: &Platform, level: OptimizationLevel) -> Result<TargetMachine, CodyError> {
+    let triple: TargetTriple = target_triple(platform);
+    // C compilers for ELF systems link position independent executables by default, as do
+    // those of most hosts
+    let reloc_mode = if platform.triple.as_deref().is_none_or(is_elf) {
+        RelocMode::PIC
+    } else {
+        RelocMode::Default
+    };
+    let target = Target::from_triple(&triple).map_err(|error| {
+        CodyError::new(ErrorCode::UnknownTarget, format!("Unknown target {}: {}", triple, error), Span::default())
+    })?;
+    let cpu = platform.cpu.as_deref().unwrap_or("generic");
+    let features = platform.features.as_deref().unwrap_or("");
+    target.create_target_machine(&triple, cpu, features, level, reloc_mode, CodeModel::Default)
+        .ok_or_else(|| {
+            let message = format!("Failed to create a target machine for {} with CPU {}.", triple, cpu);
+            CodyError::new(ErrorCode::UnknownTarget, message, Span::default())
+        })
+}
+
+/// The triple of a platform's target, initialising LLVM's support for it.
+pub fn target_triple(platform: &Platform) -> TargetTriple {
+    match &platform.triple {
+        Some(triple) => {
+            Target::initialize_all(&InitializationConfig::default());
+            TargetTriple::create(triple)
+        },
+        None => {
+            Target::initialize_native(&InitializationConfig::default()).expect("Failed to initialize native target.");
+            TargetMachine::get_default_triple()
+        },
+    }
+}
+
+/// Checks that a program can be compiled into the given kind of file for a platform, before it
+/// is compiled. The runtime needs `mmap`, `sysconf` and `setjmp`, which WebAssembly does not have,
+/// so programs for it can only be written as object files to be linked with a runtime of its own.
+pub fn check(emit: Emit, platform: &Platform) -> Result<(), CodyError> {
+    match &platform.triple {
+        Some(triple) if emit == Emit::Exe && triple.starts_with("wasm") => Err(CodyError::new(
+            ErrorCode::UnsupportedTarget,
+            format!("The runtime cannot be compiled for {}, so no executable can be linked for it.", triple),
+            Span::default()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Whether the object files of a target are ELF, as on Linux and the BSDs, rather than Mach-O,
+/// COFF or WebAssembly.
+fn is_elf(triple: &str) -> bool {
+    !["wasm", "apple", "darwin", "windows", "uefi"].iter().any(|format| triple.contains(format))
+}
+
+/// Writes the code of a module for the target machine it was optimised for.
+fn write_code(machine: &TargetMachine, module: &Module<'_>, file_type: FileType, path: &Path) -> Result<(), CodyError> {
+    machine.write_to_file(module, file_type, path).map_err(|error| write_failed(path, error))
+}
+
+/// The error for a file that could not be written, such as an output file in a directory that
+/// does not exist.
+fn write_failed(path: &Path, error: impl Display) -> CodyError {
+    CodyError::new(ErrorCode::WriteFailed, format!("Failed to write {}: {}", path.display(), error), Span::default())
+}
+
+/// Links an object file with the runtime into an executable for a platform, using a directory
+/// for the source of the runtime. The C compiler used is `cc`, unless the `CC` environment
+/// variable names another. If linking fails, the C compiler has reported why.
+fn link(directory: &Path, object: &Path, output: &str, platform: &Platform) -> Result<(), CodyError> {
+    let runtime: PathBuf = directory.join("cody_runtime.c");
+    fs::write(&runtime, RUNTIME_SOURCE).map_err(|error| write_failed(&runtime, error))?;
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let mut command = Command::new(&compiler);
+    // the runtime is compiled for the same platform, which needs a cross compiler such as clang
+    if let Some(triple) = &platform.triple {
+        command.arg(format!("--target={}", triple));
+    }
+    let status = command
+        .arg("-O2")
+        .arg(object)
+        .arg(&runtime)
+        // for the external functions of the C math library
+        .arg("-lm")
+        .arg("-o")
+        .arg(output)
+        .status()
+        .map_err(|error| {
+            let message = format!("Failed to run the C compiler {}: {}", compiler, error);
+            CodyError::new(ErrorCode::LinkFailed, message, Span::default())
+        })?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(CodyError::new(ErrorCode::LinkFailed, format!("Failed to link {}.", output), Span::default()))
+    }
+}
@@ -0,0 +1,73 @@
+//! The values of enums.
+//! A value of an enum points at a record whose first word is the number of its variant, as an
+//! integer, followed by its fields. Variants are numbered across the whole program, so that the
+//! value of one enum never matches the variants of another. The values of variants without
+//! fields are constant records outside of the heap.
+
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::module::{Linkage, Module};
+use inkwell::values::{IntValue, PointerValue};
+use inkwell::IntPredicate;
+
+use super::value::{self, word_type, RECORD_TAG};
+
+/// A variant of an enum in scope.
+#[derive(Clone, Copy, Debug)]
+pub struct Variant {
+    pub number: u64,
+    pub arity: usize,
+}
+
+/// Builds the value of a variant holding the given fields, which match its arity.
+pub fn build_value<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, variant: Variant, fields: &[IntValue<'a>]) -> IntValue<'a> {
+    if fields.is_empty() {
+        return constant(context, module, variant);
+    }
+    // the fields must survive a collection triggered by the allocation
+    let slots: Vec<PointerValue<'a>> = fields.iter().map(|field| value::spill(context, builder, *field)).collect();
+    let record: PointerValue<'a> = value::allocate(context, module, builder, fields.len() + 1, "variant");
+    value::store_field(context, builder, record, 0, value::integer(context, variant.number as i64));
+    for (i, slot) in slots.into_iter().enumerate() {
+        let field: IntValue<'a> = value::load_slot(context, builder, slot, "field");
+        value::store_field(context, builder, record, i + 1, field);
+    }
+    value::tag_pointer(context, builder, record, RECORD_TAG)
+}
+
+/// Returns the value of a variant without fields, a constant record in the module.
+fn constant<'a>(context: &'a Context, module: &Module<'a>, variant: Variant) -> IntValue<'a> {
+    let record_name = format!("cody.variant.{}", variant.number);
+    let record = module.get_global(&record_name).unwrap_or_else(|| {
+        let number: IntValue<'a> = value::integer(context, variant.number as i64);
+        let record = module.add_global(word_type(context).array_type(1), None, &record_name);
+        record.set_initializer(&word_type(context).const_array(&[number]));
+        record.set_constant(true);
+        record.set_linkage(Linkage::Private);
+        // the tag is kept in the low bits of the record's address
+        record.set_alignment(8);
+        record
+    });
+    record.as_pointer_value()
+        .const_to_int(word_type(context))
+        .const_add(word_type(context).const_int(RECORD_TAG, false))
+}
+
+/// Returns the number of the variant of a value matched on, aborting the program with a type
+/// error if it is not the value of an enum.
+pub fn build_number<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, word: IntValue<'a>) -> IntValue<'a> {
+    value::check_tag(context, module, builder, word, RECORD_TAG, "match", "a value of an enum");
+    let record: PointerValue<'a> = value::untag_pointer(context, builder, word);
+    let number: IntValue<'a> = value::load_field(context, builder, record, 0, "variant");
+    // bit-precise integers share the tag, with a negative first word
+    let is_variant: IntValue<'a> = builder.build_int_compare(IntPredicate::SGE, number, word_type(context).const_zero(), "isvariant")
+        .expect("Failed to compare variant number.");
+    value::build_type_check(context, module, builder, is_variant, word, "match", "a value of an enum");
+    number
+}
+
+/// Returns the field at `index` of the value of a variant.
+pub fn load_field<'a>(context: &'a Context, builder: &Builder<'a>, word: IntValue<'a>, index: usize, name: &str) -> IntValue<'a> {
+    let record: PointerValue<'a> = value::untag_pointer(context, builder, word);
+    value::load_field(context, builder, record, index + 1, name)
+}
@@ -0,0 +1,165 @@
+//! External functions, declared with `(extern name (parameter types) return type)`.
+//! Calls to them convert each argument from a cody value to its C type, and the result back.
+//! Integers and pointers are passed as cody integers, and floating point parameters and results
+//! as cody floats.
+
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::module::{Linkage, Module};
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType};
+use inkwell::values::{BasicMetadataValueEnum, BasicValueEnum, FloatValue, FunctionValue, IntValue};
+
+use crate::error::{CodyError, ErrorCode, Span};
+use crate::parser::node_types::{ExpressionAST, ForeignType};
+
+use super::value::{self, float_type, pointer_type, word_type, FLOAT_TAG, INTEGER_TAG};
+
+/// An external function, with the signature it was declared with.
+#[derive(Clone, Debug)]
+pub struct ForeignFunction<'a> {
+    pub function: FunctionValue<'a>,
+    pub parameter_types: Vec<ForeignType>,
+    pub return_type: ForeignType,
+}
+
+/// The LLVM type of a foreign type, `None` for void.
+fn basic_type(context: &Context, foreign_type: ForeignType) -> Option<BasicTypeEnum<'_>> {
+    match foreign_type {
+        ForeignType::I8 => Some(context.i8_type().into()),
+        ForeignType::I16 => Some(context.i16_type().into()),
+        ForeignType::I32 => Some(context.i32_type().into()),
+        ForeignType::I64 => Some(context.i64_type().into()),
+        ForeignType::F32 => Some(context.f32_type().into()),
+        ForeignType::F64 => Some(context.f64_type().into()),
+        ForeignType::Ptr => Some(pointer_type(context).into()),
+        ForeignType::Void => None,
+    }
+}
+
+/// Declares an external function in the module.
+pub fn declare<'a>(context: &'a Context, module: &Module<'a>, name: &str, parameter_types: Vec<ForeignType>, return_type: ForeignType, span: Span) -> Result<ForeignFunction<'a>, CodyError> {
+    let param_types: Vec<BasicMetadataTypeEnum<'a>> = parameter_types.iter()
+        .map(|parameter_type| basic_type(context, *parameter_type).expect("void is not a parameter type.").into())
+        .collect();
+    let function_type: FunctionType<'a> = match basic_type(context, return_type) {
+        Some(return_type) => return_type.fn_type(&param_types, false),
+        None => context.void_type().fn_type(&param_types, false),
+    };
+
+    let function: FunctionValue<'a> = match module.get_function(name) {
+        // the symbol may already have been declared, by another extern or by the compiler
+        Some(existing) if existing.get_linkage() == Linkage::External => {
+            if existing.get_type() != function_type {
+                return Err(CodyError::new(
+                    ErrorCode::TypeMismatch,
+                    format!("External function {} was already declared with a different signature.", name),
+                    span
+                ));
+            }
+            existing
+        },
+        // cody functions are internal to the module, so they give up their name to the symbol
+        Some(existing) => {
+            existing.as_global_value().set_name(&format!("{}.cody", name));
+            module.add_function(name, function_type, Some(Linkage::External))
+        },
+        None => module.add_function(name, function_type, Some(Linkage::External)),
+    };
+    Ok(ForeignFunction { function, parameter_types, return_type })
+}
+
+/// Checks a call to an external function against its signature: the number of arguments, and
+/// that no argument is a literal that can never be converted to its parameter's type.
+pub fn check_call(name: &str, span: Span, foreign: &ForeignFunction<'_>, arguments: &[ExpressionAST]) -> Result<(), CodyError> {
+    if arguments.len() != foreign.parameter_types.len() {
+        return Err(CodyError::new(
+            ErrorCode::ArityMismatch,
+            format!("External function {} expects {} arguments, but was called with {}.", name, foreign.parameter_types.len(), arguments.len()),
+            span
+        ));
+    }
+    let mismatch = arguments.iter().zip(&foreign.parameter_types).enumerate().find_map(|(i, (argument, parameter_type))| {
+        let kind = match argument {
+            ExpressionAST::NoneExpr => "()",
+            ExpressionAST::PairExpr(..) => "a pair",
+            ExpressionAST::ClosureExpr(..) | ExpressionAST::OperatorExpr(_) => "a function",
+            ExpressionAST::IntegerExpr(_) if parameter_type.is_float() => "an integer",
+            ExpressionAST::FloatExpr(_) if !parameter_type.is_float() => "a float",
+            ExpressionAST::BoolExpr(_) => "a boolean",
+            ExpressionAST::CharExpr(_) => "a character",
+            ExpressionAST::StringExpr(_) => "a string",
+            _ => return None,
+        };
+        Some((i, parameter_type, kind))
+    });
+    match mismatch {
+        Some((i, parameter_type, kind)) => Err(CodyError::new(
+            ErrorCode::TypeMismatch,
+            format!("Argument {} of external function {} must be {}, but is {}.", i + 1, name, parameter_type, kind),
+            span
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Builds a call to an external function with arguments matching its signature.
+pub fn build_call<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, foreign: &ForeignFunction<'a>, arguments: &[IntValue<'a>]) -> IntValue<'a> {
+    let name: String = foreign.function.get_name().to_string_lossy().into_owned();
+    let argument_values: Vec<BasicMetadataValueEnum<'a>> = arguments.iter()
+        .zip(&foreign.parameter_types)
+        .map(|(argument, parameter_type)| to_foreign(context, module, builder, &name, *parameter_type, *argument).into())
+        .collect();
+    let call_value = builder.build_call(foreign.function, &argument_values, "extern")
+        .expect("Failed to build call.");
+    match call_value.try_as_basic_value().left() {
+        Some(result) => from_foreign(context, module, builder, foreign.return_type, result),
+        None => value::nil(context),
+    }
+}
+
+/// Converts a cody value into an argument of the given type, aborting the program with a type
+/// error if the value is not a number of the right kind.
+fn to_foreign<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, name: &str, foreign_type: ForeignType, word: IntValue<'a>) -> BasicValueEnum<'a> {
+    match foreign_type {
+        ForeignType::F32 | ForeignType::F64 => value::check_tag(context, module, builder, word, FLOAT_TAG, name, "a float"),
+        ForeignType::Void => (),
+        _ => value::check_tag(context, module, builder, word, INTEGER_TAG, name, "an integer"),
+    }
+    match foreign_type {
+        ForeignType::I8 | ForeignType::I16 | ForeignType::I32 => {
+            let integer: IntValue<'a> = value::untag_integer(context, builder, word);
+            let int_type = basic_type(context, foreign_type).unwrap().into_int_type();
+            builder.build_int_truncate(integer, int_type, "arg").expect("Failed to convert argument.").into()
+        },
+        ForeignType::I64 => value::untag_integer(context, builder, word).into(),
+        ForeignType::F32 => {
+            let float: FloatValue<'a> = value::unbox_float(context, builder, word);
+            builder.build_float_trunc(float, context.f32_type(), "arg").expect("Failed to convert argument.").into()
+        },
+        ForeignType::F64 => value::unbox_float(context, builder, word).into(),
+        ForeignType::Ptr => {
+            let integer: IntValue<'a> = value::untag_integer(context, builder, word);
+            builder.build_int_to_ptr(integer, pointer_type(context), "arg").expect("Failed to convert argument.").into()
+        },
+        ForeignType::Void => unreachable!("void is not a parameter type."),
+    }
+}
+
+/// Converts the result of an external function into a cody value.
+fn from_foreign<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, foreign_type: ForeignType, result: BasicValueEnum<'a>) -> IntValue<'a> {
+    let integer: IntValue<'a> = match foreign_type {
+        ForeignType::I8 | ForeignType::I16 | ForeignType::I32 => builder.build_int_s_extend(result.into_int_value(), word_type(context), "result")
+            .expect("Failed to convert result."),
+        ForeignType::I64 => result.into_int_value(),
+        ForeignType::F32 => {
+            let float: FloatValue<'a> = builder.build_float_ext(result.into_float_value(), float_type(context), "result")
+                .expect("Failed to convert result.");
+            return value::box_float(context, module, builder, float);
+        },
+        ForeignType::F64 => return value::box_float(context, module, builder, result.into_float_value()),
+        ForeignType::Ptr => builder.build_ptr_to_int(result.into_pointer_value(), word_type(context), "result")
+            .expect("Failed to convert result."),
+        ForeignType::Void => unreachable!("void functions do not produce a result."),
+    };
+    value::tag_integer(context, builder, integer)
+}
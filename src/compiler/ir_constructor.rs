@@ -1,28 +1,36 @@
-use std::collections::HashMap;
-
 use inkwell::context::Context;
 use inkwell::module::Module;
-use inkwell::builder::Builder;
-use inkwell::types::{FunctionType, BasicType};
-use inkwell::values::{FunctionValue, BasicValue, IntValue};
+use inkwell::values::IntValue;
 
+use crate::error::CodyError;
 use crate::parser::node_types::ExpressionAST;
 use crate::compiler::ast_converter::Codegen;
+use crate::compiler::emit::{self, Platform};
 use crate::compiler::scope::Scope;
-pub fn construct(ast: ExpressionAST, output: &str) {
-    let context = Context::create();
-    let module = context.create_module(output);
+use crate::compiler::value;
+
+/// Builds the module of a program for a platform, whose `main` returns the program's value as its
+/// exit code.
+pub fn construct<'a>(context: &'a Context, ast: ExpressionAST, name: &str, platform: &Platform) -> Result<Module<'a>, CodyError> {
+    let module = context.create_module(name);
+    // the calling convention of functions depends on the target
+    module.set_triple(&emit::target_triple(platform));
     let builder = context.create_builder();
-    let scope = Scope::new(None);
 
     let i32_type = context.i32_type();
     let fn_type = i32_type.fn_type(&[], false);
     let fn_value = module.add_function("main", fn_type, None);
     let basic_block = context.append_basic_block(fn_value, "entry");
     builder.position_at_end(basic_block);
+    value::enter_frame(context, &module, &builder);
 
-    let ret_val = ast.codegen(&context, &builder, & scope);
-    builder.build_return(Some(&ret_val));
-
-    module.print_to_file(output).expect("Failed to write to file.");
-}
\ No newline at end of file
+    let scope = Scope::new(None, fn_value);
+    let ret_val: IntValue = ast.codegen(context, &module, &builder, &scope)?;
+    // the program's value is returned as its exit code
+    let ret_val: IntValue = value::untag_integer(context, &builder, ret_val);
+    let ret_val: IntValue = builder.build_int_truncate(ret_val, i32_type, "exitcode")
+        .expect("Failed to build exit code.");
+    value::leave_frame(context, &module, &builder);
+    builder.build_return(Some(&ret_val)).expect("Failed to build return.");
+    Ok(module)
+}
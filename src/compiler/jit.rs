@@ -0,0 +1,87 @@
+//! Running programs in memory, without writing any file.
+//! The module is compiled by LLVM's JIT, and its calls to the runtime are bound to the copy of
+//! the runtime the build script links into the compiler.
+
+use inkwell::execution_engine::{ExecutionEngine, JitFunction};
+use inkwell::module::Module;
+
+use crate::error::CodyError;
+
+use super::emit::Platform;
+use super::optimize::{self, Optimization};
+
+// only the addresses of the runtime's symbols are used, so their signatures are left out
+extern "C" {
+    fn cody_type_error();
+    fn cody_error();
+    fn cody_gc_alloc();
+    fn cody_gc_alloc_raw();
+    fn cody_gc_register_root();
+    fn cody_continuation_new();
+    fn cody_continuation_buffer();
+    fn cody_continuation_end();
+    fn cody_continuation_value();
+    fn cody_continue();
+    fn cody_string_length();
+    fn cody_string_ref();
+    fn cody_string_append();
+    fn cody_string_equal();
+    fn cody_string_less();
+    fn cody_integer_string();
+    fn cody_string_integer();
+    fn cody_print();
+    static mut cody_root_stack_top: *mut u64;
+}
+
+/// The address of every function and variable of the runtime compiled code may refer to.
+fn runtime_symbols() -> Vec<(&'static str, usize)> {
+    vec![
+        ("cody_type_error", cody_type_error as *const () as usize),
+        ("cody_error", cody_error as *const () as usize),
+        ("cody_gc_alloc", cody_gc_alloc as *const () as usize),
+        ("cody_gc_alloc_raw", cody_gc_alloc_raw as *const () as usize),
+        ("cody_gc_register_root", cody_gc_register_root as *const () as usize),
+        ("cody_continuation_new", cody_continuation_new as *const () as usize),
+        ("cody_continuation_buffer", cody_continuation_buffer as *const () as usize),
+        ("cody_continuation_end", cody_continuation_end as *const () as usize),
+        ("cody_continuation_value", cody_continuation_value as *const () as usize),
+        ("cody_continue", cody_continue as *const () as usize),
+        ("cody_string_length", cody_string_length as *const () as usize),
+        ("cody_string_ref", cody_string_ref as *const () as usize),
+        ("cody_string_append", cody_string_append as *const () as usize),
+        ("cody_string_equal", cody_string_equal as *const () as usize),
+        ("cody_string_less", cody_string_less as *const () as usize),
+        ("cody_integer_string", cody_integer_string as *const () as usize),
+        ("cody_string_integer", cody_string_integer as *const () as usize),
+        ("cody_print", cody_print as *const () as usize),
+        ("cody_root_stack_top", std::ptr::addr_of_mut!(cody_root_stack_top) as usize),
+    ]
+}
+
+/// Optimises a module at the given level, compiles it in memory and calls its `main`, returning
+/// the exit code of the program. External functions the program declares are looked up in the
+/// compiler's own process.
+pub fn run(module: &Module<'_>, optimization: Optimization) -> Result<i32, CodyError> {
+    // the program runs in this process, so it is compiled for the host
+    optimize::optimize(module, &Platform::default(), optimization)?;
+    let engine: ExecutionEngine = module.create_jit_execution_engine(optimization.code_level())
+        .expect("Failed to create execution engine.");
+    bind_runtime(&engine, module);
+    unsafe {
+        let main: JitFunction<unsafe extern "C" fn() -> i32> = engine.get_function("main")
+            .expect("Failed to find main.");
+        Ok(main.call())
+    }
+}
+
+/// Binds the declarations of the runtime in a module run by an execution engine.
+pub fn bind_runtime<'a>(engine: &ExecutionEngine<'a>, module: &Module<'a>) {
+    for (name, address) in runtime_symbols() {
+        // the module only declares the parts of the runtime it uses
+        if let Some(function) = module.get_function(name) {
+            engine.add_global_mapping(&function, address);
+        } else if let Some(global) = module.get_global(name) {
+            engine.add_global_mapping(&global, address);
+        }
+    }
+}
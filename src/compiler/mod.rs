@@ -1,9 +1,37 @@
+use inkwell::context::Context;
+
+use crate::error::CodyError;
 use crate::parser::node_types;
 
 pub mod ast_converter;
+pub mod bits;
+pub mod closure_converter;
+pub mod emit;
+pub mod enums;
+pub mod foreign;
 pub mod ir_constructor;
+pub mod jit;
+pub mod optimize;
+pub mod primitives;
 pub mod scope;
+pub mod session;
+pub mod value;
+
+/// Compiles a program for a platform into the output file, as the given kind of file, optimised
+/// at the given level.
+pub fn compile(ast: node_types::ExpressionAST, output: &str, emit: emit::Emit, platform: &emit::Platform, optimization: optimize::Optimization) -> Result<(), CodyError> {
+    emit::check(emit, platform)?;
+    let ast = closure_converter::closure_convert(ast);
+    let context = Context::create();
+    let module = ir_constructor::construct(&context, ast, output, platform)?;
+    emit::write(&module, emit, output, platform, optimization)
+}
 
-pub fn compile(ast: node_types::ExpressionAST, output: &str) {
-    ir_constructor::construct(ast, output);
+/// Compiles a program in memory, optimised at the given level, and runs it, returning its exit
+/// code.
+pub fn run(ast: node_types::ExpressionAST, name: &str, optimization: optimize::Optimization) -> Result<i32, CodyError> {
+    let ast = closure_converter::closure_convert(ast);
+    let context = Context::create();
+    let module = ir_constructor::construct(&context, ast, name, &emit::Platform::default())?;
+    jit::run(&module, optimization)
 }
\ No newline at end of file
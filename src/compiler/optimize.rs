@@ -0,0 +1,82 @@
+//! Optimisation of compiled modules, chosen with `-O`.
+//! Each level runs the default pipeline of LLVM's new pass manager for that level. The module is
+//! verified before the pipeline, whose passes assume it is valid, and after it.
+
+use clap::ValueEnum;
+use inkwell::module::Module;
+use inkwell::passes::PassBuilderOptions;
+use inkwell::targets::TargetMachine;
+use inkwell::OptimizationLevel;
+
+use crate::error::{CodyError, ErrorCode, Span};
+
+use super::emit::{self, Platform};
+
+/// The levels a program can be optimised at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Optimization {
+    /// No optimisation
+    #[value(name = "0")]
+    None,
+    /// Optimise quickly
+    #[value(name = "1")]
+    Less,
+    /// Optimise for speed
+    #[value(name = "2")]
+    Default,
+    /// Optimise for speed, aggressively
+    #[value(name = "3")]
+    Aggressive,
+    /// Optimise for size
+    #[value(name = "s")]
+    Size,
+}
+
+impl Optimization {
+    /// The pass pipeline run at this level.
+    fn pipeline(self) -> &'static str {
+        match self {
+            Optimization::None => "default<O0>",
+            Optimization::Less => "default<O1>",
+            Optimization::Default => "default<O2>",
+            Optimization::Aggressive => "default<O3>",
+            Optimization::Size => "default<Os>",
+        }
+    }
+
+    /// The level machine code is generated at.
+    pub fn code_level(self) -> OptimizationLevel {
+        match self {
+            Optimization::None => OptimizationLevel::None,
+            Optimization::Less => OptimizationLevel::Less,
+            Optimization::Default | Optimization::Size => OptimizationLevel::Default,
+            Optimization::Aggressive => OptimizationLevel::Aggressive,
+        }
+    }
+}
+
+/// Optimises a module for a platform at the given level, and verifies it. Returns the target
+/// machine it was optimised for, which its code is generated by.
+pub fn optimize(module: &Module<'_>, platform: &Platform, optimization: Optimization) -> Result<TargetMachine, CodyError> {
+    let machine: TargetMachine = emit::target_machine(platform, optimization.code_level())?;
+    // the passes rely on the layout of the target's types
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+    verify(module, "before optimisation")?;
+    module.run_passes(optimization.pipeline(), &machine, PassBuilderOptions::create())
+        .map_err(|error| {
+            let message = format!("Failed to run the passes of {}: {}", optimization.pipeline(), error);
+            CodyError::new(ErrorCode::PassesFailed, message, Span::default())
+        })?;
+    verify(module, "after optimisation")?;
+    Ok(machine)
+}
+
+/// Checks that a module is valid, failing with the message of LLVM's verifier if it is not. An
+/// invalid module is a bug in the compiler rather than in the program.
+fn verify(module: &Module<'_>, stage: &str) -> Result<(), CodyError> {
+    module.verify().map_err(|error| {
+        let message = format!("The compiled module is invalid {}:\n{}", stage, error);
+        CodyError::new(ErrorCode::InvalidModule, message, Span::default())
+    })
+}
@@ -0,0 +1,295 @@
+//! Primitive functions built into the compiler.
+//! A primitive is referred to by name like any other function. Calls to it are generated inline,
+//! and using it as a value refers to a wrapper function generated on demand.
+
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::module::Module;
+use inkwell::types::BasicMetadataTypeEnum;
+use inkwell::values::{BasicMetadataValueEnum, FloatValue, FunctionValue, IntValue, PointerValue};
+
+use crate::parser::token_types::BitType;
+
+use super::bits::{self, Arithmetic, Bitwise, Overflow};
+use super::value::{self, BOOLEAN, CHARACTER, FLOAT_TAG, INTEGER_TAG, PAIR_TAG, STRING_TAG, SYMBOL_TAG};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Primitive {
+    // pairs
+    Cons,
+    Car,
+    Cdr,
+    IsPair,
+    IsNil,
+    // symbols
+    IsSymbol,
+    // floats
+    IntToFloat,
+    FloatToInt,
+    IsFloat,
+    // booleans
+    Not,
+    IsBoolean,
+    // characters
+    CharToInt,
+    IntToChar,
+    IsChar,
+    // strings
+    StringLength,
+    StringRef,
+    StringAppend,
+    StringEqual,
+    StringLess,
+    IntegerToString,
+    StringToInteger,
+    Print,
+    IsString,
+    // bit-precise integers
+    BitArithmetic(Arithmetic, Overflow),
+    BitDiv,
+    BitRem,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+    BitEqual,
+    BitLess,
+    BitsToInt,
+    ToBits(BitType),
+}
+
+impl Primitive {
+    /// Looks up the primitive with the given name.
+    pub fn from_name(name: &str) -> Option<Primitive> {
+        match name {
+            "cons" => Some(Primitive::Cons),
+            "car" => Some(Primitive::Car),
+            "cdr" => Some(Primitive::Cdr),
+            "pair?" => Some(Primitive::IsPair),
+            "nil?" => Some(Primitive::IsNil),
+            "symbol?" => Some(Primitive::IsSymbol),
+            "float" => Some(Primitive::IntToFloat),
+            "truncate" => Some(Primitive::FloatToInt),
+            "float?" => Some(Primitive::IsFloat),
+            "not" => Some(Primitive::Not),
+            "boolean?" => Some(Primitive::IsBoolean),
+            "char-code" => Some(Primitive::CharToInt),
+            "code-char" => Some(Primitive::IntToChar),
+            "char?" => Some(Primitive::IsChar),
+            "string-length" => Some(Primitive::StringLength),
+            "string-ref" => Some(Primitive::StringRef),
+            "string-append" => Some(Primitive::StringAppend),
+            "string-equal?" => Some(Primitive::StringEqual),
+            "string-less?" => Some(Primitive::StringLess),
+            "integer-string" => Some(Primitive::IntegerToString),
+            "string-integer" => Some(Primitive::StringToInteger),
+            "print" => Some(Primitive::Print),
+            "string?" => Some(Primitive::IsString),
+            "wrapping-add" => Some(Primitive::BitArithmetic(Arithmetic::Add, Overflow::Wrapping)),
+            "wrapping-sub" => Some(Primitive::BitArithmetic(Arithmetic::Sub, Overflow::Wrapping)),
+            "wrapping-mul" => Some(Primitive::BitArithmetic(Arithmetic::Mul, Overflow::Wrapping)),
+            "checked-add" => Some(Primitive::BitArithmetic(Arithmetic::Add, Overflow::Checked)),
+            "checked-sub" => Some(Primitive::BitArithmetic(Arithmetic::Sub, Overflow::Checked)),
+            "checked-mul" => Some(Primitive::BitArithmetic(Arithmetic::Mul, Overflow::Checked)),
+            "saturating-add" => Some(Primitive::BitArithmetic(Arithmetic::Add, Overflow::Saturating)),
+            "saturating-sub" => Some(Primitive::BitArithmetic(Arithmetic::Sub, Overflow::Saturating)),
+            "saturating-mul" => Some(Primitive::BitArithmetic(Arithmetic::Mul, Overflow::Saturating)),
+            "bits-div" => Some(Primitive::BitDiv),
+            "bits-rem" => Some(Primitive::BitRem),
+            "bit-and" => Some(Primitive::BitAnd),
+            "bit-or" => Some(Primitive::BitOr),
+            "bit-xor" => Some(Primitive::BitXor),
+            "bit-not" => Some(Primitive::BitNot),
+            "shift-left" => Some(Primitive::ShiftLeft),
+            "shift-right" => Some(Primitive::ShiftRight),
+            "bits-equal?" => Some(Primitive::BitEqual),
+            "bits-less?" => Some(Primitive::BitLess),
+            "integer" => Some(Primitive::BitsToInt),
+            // conversions are named after their type, such as u8
+            _ => BitType::from_name(name).map(Primitive::ToBits),
+        }
+    }
+
+    /// The name of the wrapper function used when the primitive is a value.
+    pub fn function_name(&self) -> String {
+        let name = match self {
+            Primitive::Cons => "cody.cons",
+            Primitive::Car => "cody.car",
+            Primitive::Cdr => "cody.cdr",
+            Primitive::IsPair => "cody.is_pair",
+            Primitive::IsNil => "cody.is_nil",
+            Primitive::IsSymbol => "cody.is_symbol",
+            Primitive::IntToFloat => "cody.float",
+            Primitive::FloatToInt => "cody.truncate",
+            Primitive::IsFloat => "cody.is_float",
+            Primitive::Not => "cody.not_boolean",
+            Primitive::IsBoolean => "cody.is_boolean",
+            Primitive::CharToInt => "cody.char_code",
+            Primitive::IntToChar => "cody.code_char",
+            Primitive::IsChar => "cody.is_char",
+            Primitive::StringLength => "cody.string_length",
+            Primitive::StringRef => "cody.string_ref",
+            Primitive::StringAppend => "cody.string_append",
+            Primitive::StringEqual => "cody.string_equal",
+            Primitive::StringLess => "cody.string_less",
+            Primitive::IntegerToString => "cody.integer_string",
+            Primitive::StringToInteger => "cody.string_integer",
+            Primitive::Print => "cody.print",
+            Primitive::IsString => "cody.is_string",
+            Primitive::BitArithmetic(arithmetic, overflow) => return format!("cody.{}", arithmetic.name(*overflow).replace('-', "_")),
+            Primitive::BitDiv => "cody.bits_div",
+            Primitive::BitRem => "cody.bits_rem",
+            Primitive::BitAnd => "cody.bit_and",
+            Primitive::BitOr => "cody.bit_or",
+            Primitive::BitXor => "cody.bit_xor",
+            Primitive::BitNot => "cody.bit_not",
+            Primitive::ShiftLeft => "cody.shift_left",
+            Primitive::ShiftRight => "cody.shift_right",
+            Primitive::BitEqual => "cody.bits_equal",
+            Primitive::BitLess => "cody.bits_less",
+            Primitive::BitsToInt => "cody.integer",
+            Primitive::ToBits(bit_type) => return format!("cody.{}", bit_type),
+        };
+        name.to_string()
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Primitive::Cons => 2,
+            Primitive::Car | Primitive::Cdr | Primitive::IsPair | Primitive::IsNil | Primitive::IsSymbol => 1,
+            Primitive::IntToFloat | Primitive::FloatToInt | Primitive::IsFloat => 1,
+            Primitive::Not | Primitive::IsBoolean | Primitive::CharToInt | Primitive::IntToChar | Primitive::IsChar => 1,
+            Primitive::StringRef | Primitive::StringAppend | Primitive::StringEqual | Primitive::StringLess => 2,
+            Primitive::StringLength | Primitive::IntegerToString | Primitive::StringToInteger | Primitive::Print | Primitive::IsString => 1,
+            Primitive::BitArithmetic(..) | Primitive::BitDiv | Primitive::BitRem | Primitive::BitAnd | Primitive::BitOr | Primitive::BitXor => 2,
+            Primitive::ShiftLeft | Primitive::ShiftRight | Primitive::BitEqual | Primitive::BitLess => 2,
+            Primitive::BitNot | Primitive::BitsToInt | Primitive::ToBits(_) => 1,
+        }
+    }
+
+    /// Builds a call to the primitive with the given arguments, which match its arity.
+    /// Arguments of the wrong type abort the program with a type error.
+    pub fn build<'a>(&self, context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, arguments: &[IntValue<'a>]) -> IntValue<'a> {
+        match self {
+            Primitive::Cons => build_pair(context, module, builder, arguments[0], arguments[1]),
+            Primitive::Car => {
+                value::check_tag(context, module, builder, arguments[0], PAIR_TAG, "car", "a pair");
+                let record: PointerValue<'a> = value::untag_pointer(context, builder, arguments[0]);
+                value::load_field(context, builder, record, 0, "car")
+            },
+            Primitive::Cdr => {
+                value::check_tag(context, module, builder, arguments[0], PAIR_TAG, "cdr", "a pair");
+                let record: PointerValue<'a> = value::untag_pointer(context, builder, arguments[0]);
+                value::load_field(context, builder, record, 1, "cdr")
+            },
+            Primitive::IsPair => {
+                let is_pair: IntValue<'a> = value::has_tag(context, builder, arguments[0], PAIR_TAG);
+                value::from_bool(context, builder, is_pair)
+            },
+            Primitive::IsNil => {
+                let is_nil: IntValue<'a> = builder.build_int_compare(inkwell::IntPredicate::EQ, arguments[0], value::nil(context), "isnil")
+                    .expect("Failed to compare with nil.");
+                value::from_bool(context, builder, is_nil)
+            },
+            Primitive::IsSymbol => {
+                let is_symbol: IntValue<'a> = value::has_tag(context, builder, arguments[0], SYMBOL_TAG);
+                value::from_bool(context, builder, is_symbol)
+            },
+            Primitive::IntToFloat => {
+                value::check_tag(context, module, builder, arguments[0], INTEGER_TAG, "float", "an integer");
+                let integer: IntValue<'a> = value::untag_integer(context, builder, arguments[0]);
+                let float: FloatValue<'a> = builder.build_signed_int_to_float(integer, value::float_type(context), "float")
+                    .expect("Failed to convert integer to float.");
+                value::box_float(context, module, builder, float)
+            },
+            // rounds towards zero
+            Primitive::FloatToInt => {
+                value::check_tag(context, module, builder, arguments[0], FLOAT_TAG, "truncate", "a float");
+                let float: FloatValue<'a> = value::unbox_float(context, builder, arguments[0]);
+                let integer: IntValue<'a> = builder.build_float_to_signed_int(float, value::word_type(context), "truncated")
+                    .expect("Failed to convert float to integer.");
+                value::tag_integer(context, builder, integer)
+            },
+            Primitive::IsFloat => {
+                let is_float: IntValue<'a> = value::has_tag(context, builder, arguments[0], FLOAT_TAG);
+                value::from_bool(context, builder, is_float)
+            },
+            Primitive::Not => {
+                let is_false: IntValue<'a> = builder.build_int_compare(inkwell::IntPredicate::EQ, arguments[0], value::boolean(context, false), "not")
+                    .expect("Failed to compare with false.");
+                value::from_bool(context, builder, is_false)
+            },
+            Primitive::IsBoolean => {
+                let is_boolean: IntValue<'a> = value::has_kind(context, builder, arguments[0], BOOLEAN);
+                value::from_bool(context, builder, is_boolean)
+            },
+            Primitive::CharToInt => {
+                value::check_kind(context, module, builder, arguments[0], CHARACTER, "char-code", "a character");
+                let code: IntValue<'a> = value::untag_character(context, builder, arguments[0]);
+                value::tag_integer(context, builder, code)
+            },
+            Primitive::IntToChar => {
+                value::check_tag(context, module, builder, arguments[0], INTEGER_TAG, "code-char", "an integer");
+                let code: IntValue<'a> = value::untag_integer(context, builder, arguments[0]);
+                value::tag_character(context, builder, code)
+            },
+            Primitive::IsChar => {
+                let is_char: IntValue<'a> = value::has_kind(context, builder, arguments[0], CHARACTER);
+                value::from_bool(context, builder, is_char)
+            },
+            // the operations on strings are in the runtime
+            Primitive::StringLength => build_runtime_call(context, module, builder, "cody_string_length", arguments),
+            Primitive::StringRef => build_runtime_call(context, module, builder, "cody_string_ref", arguments),
+            Primitive::StringAppend => build_runtime_call(context, module, builder, "cody_string_append", arguments),
+            Primitive::StringEqual => build_runtime_call(context, module, builder, "cody_string_equal", arguments),
+            Primitive::StringLess => build_runtime_call(context, module, builder, "cody_string_less", arguments),
+            Primitive::IntegerToString => build_runtime_call(context, module, builder, "cody_integer_string", arguments),
+            Primitive::StringToInteger => build_runtime_call(context, module, builder, "cody_string_integer", arguments),
+            Primitive::Print => build_runtime_call(context, module, builder, "cody_print", arguments),
+            Primitive::IsString => {
+                let is_string: IntValue<'a> = value::has_tag(context, builder, arguments[0], STRING_TAG);
+                value::from_bool(context, builder, is_string)
+            },
+            Primitive::BitArithmetic(arithmetic, overflow) => bits::build_arithmetic(context, module, builder, *arithmetic, *overflow, arguments[0], arguments[1]),
+            Primitive::BitDiv => bits::build_division(context, module, builder, false, arguments[0], arguments[1]),
+            Primitive::BitRem => bits::build_division(context, module, builder, true, arguments[0], arguments[1]),
+            Primitive::BitAnd => bits::build_bitwise(context, module, builder, Bitwise::And, arguments[0], arguments[1]),
+            Primitive::BitOr => bits::build_bitwise(context, module, builder, Bitwise::Or, arguments[0], arguments[1]),
+            Primitive::BitXor => bits::build_bitwise(context, module, builder, Bitwise::Xor, arguments[0], arguments[1]),
+            Primitive::BitNot => bits::build_not(context, module, builder, arguments[0]),
+            Primitive::ShiftLeft => bits::build_shift(context, module, builder, true, arguments[0], arguments[1]),
+            Primitive::ShiftRight => bits::build_shift(context, module, builder, false, arguments[0], arguments[1]),
+            Primitive::BitEqual => bits::build_comparison(context, module, builder, false, arguments[0], arguments[1]),
+            Primitive::BitLess => bits::build_comparison(context, module, builder, true, arguments[0], arguments[1]),
+            Primitive::BitsToInt => bits::build_to_integer(context, module, builder, arguments[0]),
+            Primitive::ToBits(bit_type) => bits::build_conversion(context, module, builder, *bit_type, arguments[0]),
+        }
+    }
+}
+
+/// Calls a function of the runtime taking values and returning a value.
+fn build_runtime_call<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, name: &str, arguments: &[IntValue<'a>]) -> IntValue<'a> {
+    let parameter_types: Vec<BasicMetadataTypeEnum<'a>> = arguments.iter().map(|_| value::word_type(context).into()).collect();
+    let function: FunctionValue<'a> = value::runtime_function(module, name, value::word_type(context).fn_type(&parameter_types, false));
+    let arguments: Vec<BasicMetadataValueEnum<'a>> = arguments.iter().map(|argument| (*argument).into()).collect();
+    builder.build_call(function, &arguments, "runtime")
+        .expect("Failed to build call.")
+        .try_as_basic_value().left()
+        .expect("Call did not produce a value.")
+        .into_int_value()
+}
+
+/// Allocates a pair on the heap.
+pub fn build_pair<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, head: IntValue<'a>, tail: IntValue<'a>) -> IntValue<'a> {
+    // the head and tail must survive a collection triggered by the allocation
+    let head_slot: PointerValue<'a> = value::spill(context, builder, head);
+    let tail_slot: PointerValue<'a> = value::spill(context, builder, tail);
+    let record: PointerValue<'a> = value::allocate(context, module, builder, 2, "pair");
+    let head: IntValue<'a> = value::load_slot(context, builder, head_slot, "head");
+    let tail: IntValue<'a> = value::load_slot(context, builder, tail_slot, "tail");
+    value::store_field(context, builder, record, 0, head);
+    value::store_field(context, builder, record, 1, tail);
+    value::tag_pointer(context, builder, record, PAIR_TAG)
+}
@@ -1,33 +1,160 @@
-use std::{collections::HashMap, cell::RefCell};
+use std::{collections::HashMap, cell::{Cell, RefCell}};
 
-use inkwell::values::PointerValue;
+use inkwell::values::{FunctionValue, PointerValue};
 
-impl<'a> Scope<'a> {
-    pub fn new(parent: Option<Box<Scope<'a>>>) -> Scope<'a> {
+use super::enums::Variant;
+use super::foreign::ForeignFunction;
+
+impl<'s, 'a> Scope<'s, 'a> {
+    /// Creates a new scope whose code is emitted into `function`.
+    /// A scope without a parent is the global scope: its variables are module globals.
+    pub fn new(parent: Option<&'s Scope<'s, 'a>>, function: FunctionValue<'a>) -> Scope<'s, 'a> {
         Scope {
             parent,
-            variables: RefCell::new(HashMap::new())
+            function,
+            variables: RefCell::new(HashMap::new()),
+            functions: RefCell::new(HashMap::new()),
+            externs: RefCell::new(HashMap::new()),
+            variants: RefCell::new(HashMap::new()),
+            variant_count: Cell::new(0)
         }
     }
 
     pub fn add_variable(&self, name: String, value: PointerValue<'a>) {
+        // a new binding shadows any function previously known under the same name
+        self.functions.borrow_mut().remove(&name);
+        self.externs.borrow_mut().remove(&name);
+        self.variants.borrow_mut().remove(&name);
         let mut vars = self.variables.borrow_mut();
         vars.insert(name, value);
     }
 
-    pub fn get_variable(&self, name: &str) -> Option<PointerValue<'a>> {
+    /// Binds a variable that is statically known to hold `function`,
+    /// so that calls through it can be emitted directly.
+    pub fn add_function(&self, name: String, value: PointerValue<'a>, function: FunctionValue<'a>) {
+        self.add_variable(name.clone(), value);
+        self.functions.borrow_mut().insert(name, function);
+    }
+
+    /// Binds an external function, which is called directly rather than through a variable.
+    pub fn add_extern(&self, name: String, function: ForeignFunction<'a>) {
+        self.variables.borrow_mut().remove(&name);
+        self.functions.borrow_mut().remove(&name);
+        self.variants.borrow_mut().remove(&name);
+        self.externs.borrow_mut().insert(name, function);
+    }
+
+    /// Binds the constructor of a variant taking `arity` fields, numbering the variant.
+    pub fn add_variant(&self, name: String, arity: usize) -> Variant {
+        self.variables.borrow_mut().remove(&name);
+        self.functions.borrow_mut().remove(&name);
+        self.externs.borrow_mut().remove(&name);
+        let variant = Variant { number: self.next_variant_number(), arity };
+        self.variants.borrow_mut().insert(name, variant);
+        variant
+    }
+
+    /// Binds the constructor of a variant numbered before, by an earlier form of the REPL.
+    pub fn restore_variant(&self, name: String, variant: Variant) {
+        self.variables.borrow_mut().remove(&name);
+        self.functions.borrow_mut().remove(&name);
+        self.externs.borrow_mut().remove(&name);
+        self.variants.borrow_mut().insert(name, variant);
+    }
+
+    /// The number of variants numbered so far, counted by the global scope.
+    pub fn variant_count(&self) -> u64 {
+        match &self.parent {
+            Some(p) => p.variant_count(),
+            None => self.variant_count.get()
+        }
+    }
+
+    /// Numbers the next variants after those of the earlier forms of the REPL.
+    pub fn set_variant_count(&self, count: u64) {
+        match &self.parent {
+            Some(p) => p.set_variant_count(count),
+            None => self.variant_count.set(count)
+        }
+    }
+
+    /// Numbers the variants of the program in order, from the global scope.
+    fn next_variant_number(&self) -> u64 {
+        match &self.parent {
+            Some(p) => p.next_variant_number(),
+            None => {
+                let number = self.variant_count.get();
+                self.variant_count.set(number + 1);
+                number
+            }
+        }
+    }
+
+    /// Looks up a variable, returning its storage and the function whose stack
+    /// it lives on (`None` if the variable is a global).
+    pub fn get_variable(&self, name: &str) -> Option<(PointerValue<'a>, Option<FunctionValue<'a>>)> {
         let vars = self.variables.borrow();
         match vars.get(name) {
-            Some(v) => Some(*v),
+            Some(v) if self.is_global() => Some((*v, None)),
+            Some(v) => Some((*v, Some(self.function))),
             None => match &self.parent {
                 Some(p) => p.get_variable(name),
                 None => None
             }
         }
     }
+
+    /// Looks up the function a variable statically refers to, if it is known.
+    pub fn get_function(&self, name: &str) -> Option<FunctionValue<'a>> {
+        if self.variables.borrow().contains_key(name) {
+            return self.functions.borrow().get(name).copied();
+        }
+        match &self.parent {
+            Some(p) => p.get_function(name),
+            None => None
+        }
+    }
+
+    /// Looks up the external function a name refers to, if no variable shadows it.
+    pub fn get_extern(&self, name: &str) -> Option<ForeignFunction<'a>> {
+        if self.variables.borrow().contains_key(name) || self.variants.borrow().contains_key(name) {
+            return None;
+        }
+        if let Some(function) = self.externs.borrow().get(name) {
+            return Some(function.clone());
+        }
+        match &self.parent {
+            Some(p) => p.get_extern(name),
+            None => None
+        }
+    }
+
+    /// Looks up the variant a name refers to, if no variable or external function shadows it.
+    pub fn get_variant(&self, name: &str) -> Option<Variant> {
+        if self.variables.borrow().contains_key(name) || self.externs.borrow().contains_key(name) {
+            return None;
+        }
+        if let Some(variant) = self.variants.borrow().get(name) {
+            return Some(*variant);
+        }
+        match &self.parent {
+            Some(p) => p.get_variant(name),
+            None => None
+        }
+    }
+
+    pub fn is_global(&self) -> bool {
+        self.parent.is_none()
+    }
 }
 
-pub struct Scope<'a> {
-    pub parent: Option<Box<Scope<'a>>>,
-    pub variables: RefCell<HashMap<String, PointerValue<'a>>>
-}
\ No newline at end of file
+pub struct Scope<'s, 'a> {
+    pub parent: Option<&'s Scope<'s, 'a>>,
+    pub function: FunctionValue<'a>,
+    pub variables: RefCell<HashMap<String, PointerValue<'a>>>,
+    pub functions: RefCell<HashMap<String, FunctionValue<'a>>>,
+    pub externs: RefCell<HashMap<String, ForeignFunction<'a>>>,
+    pub variants: RefCell<HashMap<String, Variant>>,
+    /// The number of variants declared in the program, counted by the global scope.
+    variant_count: Cell<u64>
+}
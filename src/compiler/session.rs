@@ -0,0 +1,193 @@
+//! The definitions of a REPL session, which persist from one form to the next.
+//! Each form is compiled into a module of its own and added to the execution engine, where it
+//! links against the modules of the forms before it: its module declares the global variables
+//! of the definitions still in scope, and the symbols of the earlier forms, so that symbols of
+//! the same name stay the same word. Global variables are renamed after the form defining them,
+//! as a later form may define the same name again.
+
+use std::collections::HashMap;
+
+use inkwell::context::Context;
+use inkwell::execution_engine::{ExecutionEngine, JitFunction};
+use inkwell::module::{Linkage, Module};
+use inkwell::values::{FunctionValue, IntValue};
+
+use crate::error::{CodyError, Span};
+use crate::parser::node_types::{ExpressionAST, ForeignType};
+
+use super::ast_converter::Codegen;
+use super::closure_converter;
+use super::emit::{self, Platform};
+use super::enums::Variant;
+use super::foreign;
+use super::jit;
+use super::optimize::{self, Optimization};
+use super::scope::Scope;
+use super::value::{self, word_type};
+
+extern "C" {
+    fn cody_run_form(form: unsafe extern "C" fn() -> u64, result: *mut u64) -> i32;
+    fn cody_write(value: u64);
+}
+
+/// What a name defined by an earlier form refers to.
+#[derive(Clone, Debug)]
+enum Binding {
+    /// A global definition, held in the global variable with the given symbol.
+    Global(String),
+    /// An external function, with its parameter and return types.
+    Extern(Vec<ForeignType>, ForeignType),
+    Variant(Variant),
+}
+
+/// A form compiled into a module, with the definitions in scope after it.
+struct Form<'a> {
+    module: Module<'a>,
+    bindings: HashMap<String, Binding>,
+    symbols: Vec<String>,
+    variant_count: u64,
+}
+
+pub struct Session<'a> {
+    context: &'a Context,
+    /// Created with the module of the first form.
+    engine: Option<ExecutionEngine<'a>>,
+    bindings: HashMap<String, Binding>,
+    /// The names of the symbols used by the forms so far.
+    symbols: Vec<String>,
+    /// The number of variants declared by the forms so far.
+    variant_count: u64,
+    /// The number of forms compiled, which names the entry of each form.
+    forms: usize,
+    /// The level the module of each form is optimised at.
+    optimization: Optimization,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(context: &'a Context, optimization: Optimization) -> Session<'a> {
+        Session {
+            context,
+            engine: None,
+            bindings: HashMap::new(),
+            symbols: Vec::new(),
+            variant_count: 0,
+            forms: 0,
+            optimization,
+        }
+    }
+
+    /// Compiles a form and runs it, returning its value, or `None` if it ended with an error at
+    /// run time, which the runtime has reported. The definitions of the form stay in scope for
+    /// the forms after it, even if it ended with an error.
+    pub fn run(&mut self, ast: ExpressionAST) -> Result<Option<u64>, CodyError> {
+        self.forms += 1;
+        let entry_name = format!("cody.form.{}", self.forms);
+        let form: Form<'a> = self.construct(ast, &entry_name)?;
+        // a form that cannot be compiled defines nothing
+        optimize::optimize(&form.module, &Platform::default(), self.optimization)?;
+        self.bindings = form.bindings;
+        self.symbols.extend(form.symbols);
+        self.variant_count = form.variant_count;
+
+        match &self.engine {
+            Some(engine) => engine.add_module(&form.module).expect("Failed to add module to execution engine."),
+            None => self.engine = Some(form.module.create_jit_execution_engine(self.optimization.code_level())
+                .expect("Failed to create execution engine.")),
+        }
+        let engine: &ExecutionEngine<'a> = self.engine.as_ref().unwrap();
+        jit::bind_runtime(engine, &form.module);
+        let mut result: u64 = 0;
+        let completed = unsafe {
+            let entry: JitFunction<unsafe extern "C" fn() -> u64> = engine.get_function(&entry_name)
+                .expect("Failed to find the entry of the form.");
+            cody_run_form(entry.into_raw(), &mut result) != 0
+        };
+        Ok(if completed { Some(result) } else { None })
+    }
+
+    /// The LLVM IR a form compiles to, if it was run next.
+    pub fn ir(&self, ast: ExpressionAST) -> Result<String, CodyError> {
+        let form: Form<'a> = self.construct(ast, &format!("cody.form.{}", self.forms + 1))?;
+        Ok(form.module.print_to_string().to_string())
+    }
+
+    /// Builds the module of a form, whose entry returns the value of the form.
+    fn construct(&self, ast: ExpressionAST, entry_name: &str) -> Result<Form<'a>, CodyError> {
+        let context: &'a Context = self.context;
+        let module: Module<'a> = context.create_module(entry_name);
+        module.set_triple(&emit::target_triple(&Platform::default()));
+        let builder = context.create_builder();
+        let entry: FunctionValue<'a> = module.add_function(entry_name, word_type(context).fn_type(&[], false), None);
+        builder.position_at_end(context.append_basic_block(entry, "entry"));
+        value::enter_frame(context, &module, &builder);
+
+        // the symbols of earlier forms are found by name in the module
+        for symbol in &self.symbols {
+            module.add_global(context.i8_type().array_type(symbol.len() as u32 + 1), None, &format!("cody.symbol.{}", symbol));
+        }
+        let scope = Scope::new(None, entry);
+        scope.set_variant_count(self.variant_count);
+        for (name, binding) in &self.bindings {
+            match binding {
+                Binding::Global(symbol) => {
+                    let global = module.add_global(word_type(context), None, symbol);
+                    scope.add_variable(name.clone(), global.as_pointer_value());
+                },
+                Binding::Extern(parameter_types, return_type) => {
+                    let function = foreign::declare(context, &module, name, parameter_types.clone(), *return_type, Span::default())
+                        .expect("Failed to declare external function of an earlier form.");
+                    scope.add_extern(name.clone(), function);
+                },
+                Binding::Variant(variant) => scope.restore_variant(name.clone(), *variant),
+            }
+        }
+
+        let ast = closure_converter::closure_convert(ast);
+        let form_value: IntValue<'a> = ast.codegen(context, &module, &builder, &scope)?;
+        value::leave_frame(context, &module, &builder);
+        builder.build_return(Some(&form_value)).expect("Failed to build return.");
+
+        let mut bindings: HashMap<String, Binding> = HashMap::new();
+        for (name, pointer) in scope.variables.borrow().iter() {
+            let symbol = pointer.get_name().to_str().expect("Global name is not valid UTF-8.").to_string();
+            let symbol = match self.bindings.get(name) {
+                Some(Binding::Global(previous)) if *previous == symbol => symbol,
+                // defined by this form
+                _ => {
+                    let unique = format!("{}.{}", entry_name, name);
+                    // the forms after this one link against it
+                    let global = module.get_global(&symbol).expect("Global definition is not in the module.");
+                    global.set_name(&unique);
+                    global.set_linkage(Linkage::External);
+                    unique
+                },
+            };
+            bindings.insert(name.clone(), Binding::Global(symbol));
+        }
+        for (name, function) in scope.externs.borrow().iter() {
+            bindings.insert(name.clone(), Binding::Extern(function.parameter_types.clone(), function.return_type));
+        }
+        for (name, variant) in scope.variants.borrow().iter() {
+            bindings.insert(name.clone(), Binding::Variant(*variant));
+        }
+
+        // the symbols this form defines are shared with the forms after it
+        let mut symbols: Vec<String> = Vec::new();
+        for global in module.get_globals() {
+            let global_name = global.get_name().to_str().expect("Global name is not valid UTF-8.").to_string();
+            if let Some(symbol) = global_name.strip_prefix("cody.symbol.") {
+                if global.get_initializer().is_some() {
+                    global.set_linkage(Linkage::External);
+                    symbols.push(symbol.to_string());
+                }
+            }
+        }
+        let variant_count = scope.variant_count();
+        Ok(Form { module, bindings, symbols, variant_count })
+    }
+}
+
+/// Writes a value to stdout the way it is written in a program.
+pub fn write(value: u64) {
+    unsafe { cody_write(value) }
+}
@@ -0,0 +1,437 @@
+//! The uniform representation of cody values.
+//! Every value is a single 64-bit word. The low three bits of the word are a tag saying what
+//! kind of value it is, and the remaining bits hold either the value itself or, for values
+//! living on the heap, the address of an 8-byte aligned record of words.
+//!
+//! The heap is managed by the garbage collector in the runtime (`runtime/cody_runtime.c`).
+//! It finds the values in use through GC roots: every variable is stored in a slot of its
+//! function's frame on the runtime's root stack, and values held while evaluating further
+//! expressions are spilled into one.
+
+use inkwell::AddressSpace;
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::module::{Linkage, Module};
+use inkwell::types::{FloatType, FunctionType, IntType, PointerType};
+use inkwell::values::{FloatValue, FunctionValue, InstructionValue, IntValue, PointerValue};
+
+pub const TAG_BITS: u64 = 3;
+pub const TAG_MASK: u64 = 0b111;
+
+/// Integers, stored shifted left by the tag bits.
+pub const INTEGER_TAG: u64 = 0b000;
+/// Pairs, pointing at a record of their head and tail.
+pub const PAIR_TAG: u64 = 0b001;
+/// Closures, pointing at a record of their code, the number of arguments it takes as an integer,
+/// and their captured variables.
+pub const CLOSURE_TAG: u64 = 0b010;
+/// Symbols, pointing at their name, a constant string interned per module.
+pub const SYMBOL_TAG: u64 = 0b011;
+/// Floats, pointing at a record holding their `f64` value.
+pub const FLOAT_TAG: u64 = 0b100;
+/// Strings, pointing at a record of their length in bytes, followed by their bytes and a NUL.
+pub const STRING_TAG: u64 = 0b101;
+/// Values pointing at a record described by its first word: values of enums, whose record starts
+/// with the number of their variant, and bit-precise integers, whose record starts with their
+/// negated type code.
+pub const RECORD_TAG: u64 = 0b110;
+/// Values without a payload on the heap. The bits above the tag say what kind of immediate a
+/// value is, and the bits above those hold its payload.
+pub const IMMEDIATE_TAG: u64 = 0b111;
+
+/// The number of bits below the payload of an immediate.
+pub const IMMEDIATE_BITS: u64 = 8;
+pub const IMMEDIATE_MASK: u64 = 0xff;
+
+/// The empty value `()`, which ends lists.
+pub const NIL: u64 = IMMEDIATE_TAG;
+/// Booleans, `false` with a payload of 0 and `true` with a payload of 1.
+pub const BOOLEAN: u64 = 1 << TAG_BITS | IMMEDIATE_TAG;
+pub const FALSE: u64 = BOOLEAN;
+pub const TRUE: u64 = 1 << IMMEDIATE_BITS | BOOLEAN;
+/// Characters, with their unicode scalar value as payload.
+pub const CHARACTER: u64 = 2 << TAG_BITS | IMMEDIATE_TAG;
+
+pub fn word_type(context: &Context) -> IntType<'_> {
+    context.i64_type()
+}
+
+pub fn pointer_type(context: &Context) -> PointerType<'_> {
+    context.i8_type().ptr_type(AddressSpace::default())
+}
+
+pub fn float_type(context: &Context) -> FloatType<'_> {
+    context.f64_type()
+}
+
+/// An integer constant.
+pub fn integer(context: &Context, i: i64) -> IntValue<'_> {
+    word_type(context).const_int((i << TAG_BITS) as u64, true)
+}
+
+pub fn nil(context: &Context) -> IntValue<'_> {
+    word_type(context).const_int(NIL, false)
+}
+
+pub fn boolean(context: &Context, b: bool) -> IntValue<'_> {
+    word_type(context).const_int(if b { TRUE } else { FALSE }, false)
+}
+
+/// A character constant.
+pub fn character(context: &Context, c: char) -> IntValue<'_> {
+    word_type(context).const_int((c as u64) << IMMEDIATE_BITS | CHARACTER, false)
+}
+
+/// A float constant, a constant record interned per module.
+pub fn float<'a>(context: &'a Context, module: &Module<'a>, f: f64) -> IntValue<'a> {
+    let global_name = format!("cody.float.{:x}", f.to_bits());
+    let global = module.get_global(&global_name).unwrap_or_else(|| {
+        let global = module.add_global(float_type(context), None, &global_name);
+        global.set_initializer(&float_type(context).const_float(f));
+        global.set_constant(true);
+        global.set_linkage(Linkage::Private);
+        global.set_alignment(8);
+        global
+    });
+    global.as_pointer_value()
+        .const_to_int(word_type(context))
+        .const_add(word_type(context).const_int(FLOAT_TAG, false))
+}
+
+/// A string constant, a constant record in the module.
+pub fn string<'a>(context: &'a Context, module: &Module<'a>, s: &str) -> IntValue<'a> {
+    let length: IntValue<'a> = word_type(context).const_int(s.len() as u64, false);
+    let bytes = context.const_string(s.as_bytes(), true);
+    let record = context.const_struct(&[length.into(), bytes.into()], false);
+    let global = module.add_global(record.get_type(), None, "cody.string");
+    global.set_initializer(&record);
+    global.set_constant(true);
+    global.set_linkage(Linkage::Private);
+    global.set_alignment(8);
+    global.as_pointer_value()
+        .const_to_int(word_type(context))
+        .const_add(word_type(context).const_int(STRING_TAG, false))
+}
+
+/// Allocates a record holding a float on the heap.
+pub fn box_float<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, value: FloatValue<'a>) -> IntValue<'a> {
+    let record: PointerValue<'a> = allocate_raw(context, module, builder, 8, "float");
+    builder.build_store(record, value).expect("Failed to store float.");
+    tag_pointer(context, builder, record, FLOAT_TAG)
+}
+
+/// Loads the value of a float.
+pub fn unbox_float<'a>(context: &'a Context, builder: &Builder<'a>, word: IntValue<'a>) -> FloatValue<'a> {
+    let record: PointerValue<'a> = untag_pointer(context, builder, word);
+    builder.build_load(float_type(context), record, "unboxed")
+        .expect("Failed to load float.")
+        .into_float_value()
+}
+
+/// The symbol with the given name.
+/// Symbols with the same name are the same word, so they can be compared with `$=`.
+pub fn symbol<'a>(context: &'a Context, module: &Module<'a>, name: &str) -> IntValue<'a> {
+    let global_name = format!("cody.symbol.{}", name);
+    let global = module.get_global(&global_name).unwrap_or_else(|| {
+        let string = context.const_string(name.as_bytes(), true);
+        let global = module.add_global(string.get_type(), None, &global_name);
+        global.set_initializer(&string);
+        global.set_constant(true);
+        global.set_linkage(Linkage::Private);
+        // the tag is kept in the low bits of the name's address
+        global.set_alignment(8);
+        global
+    });
+    global.as_pointer_value()
+        .const_to_int(word_type(context))
+        .const_add(word_type(context).const_int(SYMBOL_TAG, false))
+}
+
+/// Converts a machine integer into an integer value.
+pub fn tag_integer<'a>(context: &'a Context, builder: &Builder<'a>, value: IntValue<'a>) -> IntValue<'a> {
+    let tag_bits: IntValue<'a> = word_type(context).const_int(TAG_BITS, false);
+    builder.build_left_shift(value, tag_bits, "int").expect("Failed to tag integer.")
+}
+
+/// Converts an integer value into a machine integer.
+pub fn untag_integer<'a>(context: &'a Context, builder: &Builder<'a>, word: IntValue<'a>) -> IntValue<'a> {
+    let tag_bits: IntValue<'a> = word_type(context).const_int(TAG_BITS, false);
+    builder.build_right_shift(word, tag_bits, true, "untagged").expect("Failed to untag integer.")
+}
+
+/// Converts an `i1` into a boolean.
+pub fn from_bool<'a>(context: &'a Context, builder: &Builder<'a>, value: IntValue<'a>) -> IntValue<'a> {
+    let value: IntValue<'a> = builder.build_int_z_extend(value, word_type(context), "bool")
+        .expect("Failed to extend boolean.");
+    let payload: IntValue<'a> = builder.build_left_shift(value, word_type(context).const_int(IMMEDIATE_BITS, false), "payload")
+        .expect("Failed to tag boolean.");
+    builder.build_or(payload, word_type(context).const_int(BOOLEAN, false), "boolean")
+        .expect("Failed to tag boolean.")
+}
+
+/// Tests whether a value is anything but `false`, returning an `i1`.
+/// Every other value, including 0 and (), counts as true in conditions.
+pub fn to_bool<'a>(context: &'a Context, builder: &Builder<'a>, word: IntValue<'a>) -> IntValue<'a> {
+    builder.build_int_compare(inkwell::IntPredicate::NE, word, word_type(context).const_int(FALSE, false), "truthy")
+        .expect("Failed to test boolean.")
+}
+
+/// Converts a machine integer holding a unicode scalar value into a character.
+pub fn tag_character<'a>(context: &'a Context, builder: &Builder<'a>, code: IntValue<'a>) -> IntValue<'a> {
+    let payload: IntValue<'a> = builder.build_left_shift(code, word_type(context).const_int(IMMEDIATE_BITS, false), "payload")
+        .expect("Failed to tag character.");
+    builder.build_or(payload, word_type(context).const_int(CHARACTER, false), "char")
+        .expect("Failed to tag character.")
+}
+
+/// Converts a character into the machine integer holding its unicode scalar value.
+pub fn untag_character<'a>(context: &'a Context, builder: &Builder<'a>, word: IntValue<'a>) -> IntValue<'a> {
+    builder.build_right_shift(word, word_type(context).const_int(IMMEDIATE_BITS, false), false, "code")
+        .expect("Failed to untag character.")
+}
+
+/// Tests whether an immediate value is of the given kind, such as `BOOLEAN` or `CHARACTER`,
+/// returning an `i1`.
+pub fn has_kind<'a>(context: &'a Context, builder: &Builder<'a>, word: IntValue<'a>, kind: u64) -> IntValue<'a> {
+    let word_kind: IntValue<'a> = builder.build_and(word, word_type(context).const_int(IMMEDIATE_MASK, false), "kind")
+        .expect("Failed to read immediate kind.");
+    builder.build_int_compare(inkwell::IntPredicate::EQ, word_kind, word_type(context).const_int(kind, false), "haskind")
+        .expect("Failed to compare immediate kind.")
+}
+
+/// Converts a pointer to a heap record into a value with the given tag.
+pub fn tag_pointer<'a>(context: &'a Context, builder: &Builder<'a>, pointer: PointerValue<'a>, tag: u64) -> IntValue<'a> {
+    let address: IntValue<'a> = builder.build_ptr_to_int(pointer, word_type(context), "address")
+        .expect("Failed to convert pointer to a word.");
+    builder.build_or(address, word_type(context).const_int(tag, false), "tagged")
+        .expect("Failed to tag pointer.")
+}
+
+/// Converts a value pointing to the heap back into a pointer to its record.
+pub fn untag_pointer<'a>(context: &'a Context, builder: &Builder<'a>, word: IntValue<'a>) -> PointerValue<'a> {
+    let address: IntValue<'a> = builder.build_and(word, word_type(context).const_int(!TAG_MASK, false), "address")
+        .expect("Failed to untag pointer.");
+    builder.build_int_to_ptr(address, pointer_type(context), "record")
+        .expect("Failed to convert word to a pointer.")
+}
+
+/// Tests whether a value has the given tag, returning an `i1`.
+pub fn has_tag<'a>(context: &'a Context, builder: &Builder<'a>, word: IntValue<'a>, tag: u64) -> IntValue<'a> {
+    let word_tag: IntValue<'a> = builder.build_and(word, word_type(context).const_int(TAG_MASK, false), "tag")
+        .expect("Failed to read tag.");
+    builder.build_int_compare(inkwell::IntPredicate::EQ, word_tag, word_type(context).const_int(tag, false), "hastag")
+        .expect("Failed to compare tag.")
+}
+
+/// Aborts the program with a readable message unless a value has the given tag.
+/// The message says what `operation` expected, as in `car expects a pair`, and what it was given.
+pub fn check_tag<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, word: IntValue<'a>, tag: u64, operation: &str, expected: &str) {
+    let valid: IntValue<'a> = has_tag(context, builder, word, tag);
+    build_type_check(context, module, builder, valid, word, operation, expected);
+}
+
+/// Aborts the program with a readable message unless an immediate value is of the given kind.
+pub fn check_kind<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, word: IntValue<'a>, kind: u64, operation: &str, expected: &str) {
+    let valid: IntValue<'a> = has_kind(context, builder, word, kind);
+    build_type_check(context, module, builder, valid, word, operation, expected);
+}
+
+/// Aborts the program with a type error on a value unless `valid`, an `i1`, holds.
+pub fn build_type_check<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, valid: IntValue<'a>, word: IntValue<'a>, operation: &str, expected: &str) {
+    build_check(context, builder, valid, || {
+        let type_error: FunctionValue<'a> = runtime_function(module, "cody_type_error", context.void_type().fn_type(&[pointer_type(context).into(), pointer_type(context).into(), word_type(context).into()], false));
+        let operation: PointerValue<'a> = message(context, module, operation);
+        let expected: PointerValue<'a> = message(context, module, expected);
+        builder.build_call(type_error, &[operation.into(), expected.into(), word.into()], "")
+            .expect("Failed to build call.");
+    });
+}
+
+/// Aborts the program with a message unless `valid`, an `i1`, holds.
+pub fn build_error_check<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, valid: IntValue<'a>, text: &str) {
+    build_check(context, builder, valid, || build_error(context, module, builder, text));
+}
+
+/// Aborts the program with a message. The code after it is unreachable.
+pub fn build_error<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, text: &str) {
+    let error: FunctionValue<'a> = runtime_function(module, "cody_error", context.void_type().fn_type(&[pointer_type(context).into()], false));
+    builder.build_call(error, &[message(context, module, text).into()], "")
+        .expect("Failed to build call.");
+}
+
+/// Branches on `valid`, continuing in a new block if it holds, and running the code built by
+/// `build_error`, which must not return, if it does not.
+fn build_check<'a>(context: &'a Context, builder: &Builder<'a>, valid: IntValue<'a>, build_error: impl FnOnce()) {
+    let function: FunctionValue<'a> = builder.get_insert_block()
+        .and_then(|block| block.get_parent())
+        .expect("Check is not in a function.");
+    let error_block = context.append_basic_block(function, "error");
+    let valid_block = context.append_basic_block(function, "valid");
+    builder.build_conditional_branch(valid, valid_block, error_block).expect("Failed to build branch.");
+    builder.position_at_end(error_block);
+    build_error();
+    builder.build_unreachable().expect("Failed to build unreachable.");
+    builder.position_at_end(valid_block);
+}
+
+/// A constant C string for the messages of the runtime, interned per module.
+fn message<'a>(context: &'a Context, module: &Module<'a>, text: &str) -> PointerValue<'a> {
+    let global_name = format!("cody.message.{}", text);
+    module.get_global(&global_name).unwrap_or_else(|| {
+        let string = context.const_string(text.as_bytes(), true);
+        let global = module.add_global(string.get_type(), None, &global_name);
+        global.set_initializer(&string);
+        global.set_constant(true);
+        global.set_linkage(Linkage::Private);
+        global
+    }).as_pointer_value()
+}
+
+/// Returns a function of the runtime, declaring it in the module on first use.
+pub fn runtime_function<'a>(module: &Module<'a>, name: &str, function_type: FunctionType<'a>) -> FunctionValue<'a> {
+    module.get_function(name)
+        .unwrap_or_else(|| module.add_function(name, function_type, Some(Linkage::External)))
+}
+
+/// Allocates a record of `size` words on the garbage collected heap.
+/// Allocating may run the collector, so values in use must be held in GC roots across the call.
+pub fn allocate<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, size: usize, name: &str) -> PointerValue<'a> {
+    let alloc: FunctionValue<'a> = runtime_function(module, "cody_gc_alloc", pointer_type(context).fn_type(&[word_type(context).into()], false));
+    let size: IntValue<'a> = word_type(context).const_int(size as u64, false);
+    builder.build_call(alloc, &[size.into()], name)
+        .expect("Failed to build allocation.")
+        .try_as_basic_value().left()
+        .expect("Allocation did not produce a value.")
+        .into_pointer_value()
+}
+
+/// Allocates `bytes` of data holding no values on the garbage collected heap.
+/// Allocating may run the collector, so values in use must be held in GC roots across the call.
+pub fn allocate_raw<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, bytes: usize, name: &str) -> PointerValue<'a> {
+    let alloc: FunctionValue<'a> = runtime_function(module, "cody_gc_alloc_raw", pointer_type(context).fn_type(&[word_type(context).into()], false));
+    let bytes: IntValue<'a> = word_type(context).const_int(bytes as u64, false);
+    builder.build_call(alloc, &[bytes.into()], name)
+        .expect("Failed to build allocation.")
+        .try_as_basic_value().left()
+        .expect("Allocation did not produce a value.")
+        .into_pointer_value()
+}
+
+/// The runtime's pointer to the end of the frames on the root stack.
+fn root_stack_top<'a>(context: &'a Context, module: &Module<'a>) -> PointerValue<'a> {
+    module.get_global("cody_root_stack_top")
+        .unwrap_or_else(|| module.add_global(pointer_type(context), None, "cody_root_stack_top"))
+        .as_pointer_value()
+}
+
+/// Pushes a frame for the slots of a function onto the root stack.
+/// This must be the first code of the function: its slots are found through the frame at the
+/// start of the entry block, which grows by a word for every slot added to the function.
+pub fn enter_frame<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>) {
+    let top: PointerValue<'a> = root_stack_top(context, module);
+    let frame: PointerValue<'a> = builder.build_load(pointer_type(context), top, "frame")
+        .expect("Failed to load root stack.")
+        .into_pointer_value();
+    let frame_end: PointerValue<'a> = unsafe { builder.build_in_bounds_gep(word_type(context), frame, &[word_type(context).const_zero()], "frameend") }
+        .expect("Failed to size frame.");
+    builder.build_store(top, frame_end).expect("Failed to push frame.");
+}
+
+/// Pops the frame of the current function, which must happen before it returns or makes a tail call.
+pub fn leave_frame<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>) {
+    let (frame, _) = frame(builder);
+    builder.build_store(root_stack_top(context, module), frame).expect("Failed to pop frame.");
+}
+
+/// Returns the frame of the current function, and the instruction computing its end.
+fn frame<'a>(builder: &Builder<'a>) -> (PointerValue<'a>, InstructionValue<'a>) {
+    let frame_end: InstructionValue<'a> = builder.get_insert_block()
+        .and_then(|block| block.get_parent())
+        .and_then(|function| function.get_first_basic_block())
+        .and_then(|entry_block| entry_block.get_first_instruction())
+        .and_then(|frame| frame.get_next_instruction())
+        .expect("Function has no frame.");
+    let frame: PointerValue<'a> = frame_end.get_operand(0)
+        .and_then(|operand| operand.left())
+        .expect("Function has no frame.")
+        .into_pointer_value();
+    (frame, frame_end)
+}
+
+/// Adds a slot for a value to the frame of the current function.
+/// Slots are cleared on entry to the function, so that the collector never sees stale values.
+pub fn build_slot<'a>(context: &'a Context, builder: &Builder<'a>, name: &str) -> PointerValue<'a> {
+    let (frame, frame_end) = frame(builder);
+    let index: u64 = frame_end.get_operand(1)
+        .and_then(|operand| operand.left())
+        .and_then(|size| size.into_int_value().get_zero_extended_constant())
+        .expect("Frame size is not constant.");
+    frame_end.set_operand(1, word_type(context).const_int(index + 1, false));
+
+    // slots are addressed right after the frame is pushed, so that they dominate all of their uses
+    let entry_builder: Builder<'a> = context.create_builder();
+    let frame_push: InstructionValue<'a> = frame_end.get_next_instruction().expect("Function has no frame.");
+    match frame_push.get_next_instruction() {
+        Some(instruction) => entry_builder.position_before(&instruction),
+        None => entry_builder.position_at_end(frame_push.get_parent().expect("Frame is not in a block.")),
+    }
+    let index: IntValue<'a> = word_type(context).const_int(index, false);
+    let slot: PointerValue<'a> = unsafe { entry_builder.build_in_bounds_gep(word_type(context), frame, &[index], name) }
+        .expect("Failed to address slot.");
+    entry_builder.build_store(slot, word_type(context).const_zero())
+        .expect("Failed to clear slot.");
+    slot
+}
+
+/// Adds a global slot for a value to the module, registering it as a GC root with the runtime.
+/// The slot is internal to the module and named apart from C's symbols, so that a definition such
+/// as `free` or `exit` does not clash with the functions of libc the program is linked against.
+pub fn build_global_slot<'a>(context: &'a Context, module: &Module<'a>, builder: &Builder<'a>, name: &str) -> PointerValue<'a> {
+    let global = module.add_global(word_type(context), None, &format!("cody.global.{}", name));
+    global.set_initializer(&word_type(context).const_zero());
+    global.set_linkage(Linkage::Internal);
+    let register: FunctionValue<'a> = runtime_function(module, "cody_gc_register_root", context.void_type().fn_type(&[pointer_type(context).into()], false));
+    builder.build_call(register, &[global.as_pointer_value().into()], "")
+        .expect("Failed to register GC root.");
+    global.as_pointer_value()
+}
+
+/// Loads the value held in a slot.
+pub fn load_slot<'a>(context: &'a Context, builder: &Builder<'a>, slot: PointerValue<'a>, name: &str) -> IntValue<'a> {
+    builder.build_load(word_type(context), slot, name)
+        .expect("Failed to load slot.")
+        .into_int_value()
+}
+
+/// Stores a value in a slot.
+pub fn store_slot<'a>(builder: &Builder<'a>, slot: PointerValue<'a>, value: IntValue<'a>) {
+    builder.build_store(slot, value).expect("Failed to store slot.");
+}
+
+/// Holds a value in a fresh slot, keeping it alive while other code allocates.
+pub fn spill<'a>(context: &'a Context, builder: &Builder<'a>, value: IntValue<'a>) -> PointerValue<'a> {
+    let slot: PointerValue<'a> = build_slot(context, builder, "spill");
+    store_slot(builder, slot, value);
+    slot
+}
+
+/// Returns a pointer to the word at `index` of a record.
+pub fn field_pointer<'a>(context: &'a Context, builder: &Builder<'a>, record: PointerValue<'a>, index: usize) -> PointerValue<'a> {
+    let index: IntValue<'a> = context.i64_type().const_int(index as u64, false);
+    // records are arrays of words, so any index within the record is in bounds
+    unsafe { builder.build_in_bounds_gep(word_type(context), record, &[index], "field") }
+        .expect("Failed to index record.")
+}
+
+/// Loads the word at `index` of a record.
+pub fn load_field<'a>(context: &'a Context, builder: &Builder<'a>, record: PointerValue<'a>, index: usize, name: &str) -> IntValue<'a> {
+    builder.build_load(word_type(context), field_pointer(context, builder, record, index), name)
+        .expect("Failed to load field.")
+        .into_int_value()
+}
+
+/// Stores a word at `index` of a record.
+pub fn store_field<'a>(context: &'a Context, builder: &Builder<'a>, record: PointerValue<'a>, index: usize, value: IntValue<'a>) {
+    builder.build_store(field_pointer(context, builder, record, index), value)
+        .expect("Failed to store field.");
+}
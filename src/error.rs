@@ -0,0 +1,137 @@
+//! Errors reported to the user while compiling a cody program.
+
+use std::fmt;
+
+/// A region of the source program.
+/// `start` and `end` are byte offsets, `line` and `column` are 1-based and refer to `start`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span { start, end, line, column }
+    }
+}
+
+/// The different kinds of errors a program can contain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    // lexing
+    UnexpectedCharacter,
+    InvalidLiteral,
+    UnterminatedString,
+
+    // parsing
+    UnexpectedToken,
+    UnexpectedEnd,
+    UnknownType,
+    InvalidPattern,
+
+    // macro expansion
+    NoMatchingRule,
+    InvalidMacro,
+
+    // compilation
+    UnboundVariable,
+    ArityMismatch,
+    TypeMismatch,
+    NonExhaustiveMatch,
+
+    // optimising and writing the compiled program
+    UnknownTarget,
+    WriteFailed,
+    LinkFailed,
+    PassesFailed,
+    InvalidModule,
+    UnsupportedTarget,
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            ErrorCode::UnexpectedCharacter => "E0001",
+            ErrorCode::InvalidLiteral => "E0002",
+            ErrorCode::UnterminatedString => "E0003",
+            ErrorCode::UnexpectedToken => "E0011",
+            ErrorCode::UnexpectedEnd => "E0012",
+            ErrorCode::UnknownType => "E0013",
+            ErrorCode::InvalidPattern => "E0014",
+            ErrorCode::NoMatchingRule => "E0021",
+            ErrorCode::InvalidMacro => "E0022",
+            ErrorCode::UnboundVariable => "E0101",
+            ErrorCode::ArityMismatch => "E0102",
+            ErrorCode::TypeMismatch => "E0103",
+            ErrorCode::NonExhaustiveMatch => "E0104",
+            ErrorCode::UnknownTarget => "E0201",
+            ErrorCode::WriteFailed => "E0202",
+            ErrorCode::LinkFailed => "E0203",
+            ErrorCode::PassesFailed => "E0204",
+            ErrorCode::InvalidModule => "E0205",
+            ErrorCode::UnsupportedTarget => "E0206",
+        };
+        write!(f, "{}", code)
+    }
+}
+
+/// An error in a cody program, pointing at the source it was found in.
+#[derive(Clone, Debug)]
+pub struct CodyError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub span: Span,
+}
+
+impl CodyError {
+    pub fn new(code: ErrorCode, message: impl Into<String>, span: Span) -> CodyError {
+        CodyError {
+            code,
+            message: message.into(),
+            span
+        }
+    }
+
+    /// Renders the error in the style of rustc, quoting the offending line of `source`
+    /// and underlining the span with carets. Errors without a span, such as those writing the
+    /// compiled program, are rendered without a location.
+    pub fn render(&self, source: &str, file_name: &str) -> String {
+        if self.span == Span::default() {
+            return format!("error[{}]: {}\n", self.code, self.message);
+        }
+        let line_text = source.lines().nth(self.span.line.saturating_sub(1)).unwrap_or("");
+        let gutter = " ".repeat(self.span.line.to_string().len());
+
+        // columns count characters while spans are byte offsets, so the underline is measured
+        // in characters. it stops at the end of the line for spans that cover several lines
+        let underline_start = self.span.column.saturating_sub(1);
+        let span_length = source.get(self.span.start..self.span.end)
+            .map(|text| text.chars().count())
+            .unwrap_or(0);
+        let underline_length = span_length
+            .min(line_text.chars().count().saturating_sub(underline_start))
+            .max(1);
+
+        format!(
+            "error[{}]: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+            self.code, self.message,
+            gutter, file_name, self.span.line, self.span.column,
+            gutter,
+            self.span.line, line_text,
+            gutter, " ".repeat(underline_start), "^".repeat(underline_length)
+        )
+    }
+}
+
+impl fmt::Display for CodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error[{}]: {}", self.code, self.message)?;
+        if self.span != Span::default() {
+            write!(f, " at {}:{}", self.span.line, self.span.column)?;
+        }
+        Ok(())
+    }
+}
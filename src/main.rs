@@ -1,19 +1,39 @@
 use std::{fs, process};
 use std::io::ErrorKind;
 
+mod checker;
 mod compiler;
+mod error;
 mod parser;
 mod arg_parser;
+mod repl;
 
-use arg_parser::read_args;
+use arg_parser::{read_args, Action};
 
-use crate::compiler::compile;
+use crate::checker::check;
+use crate::compiler::{compile, run};
+use crate::error::CodyError;
 use crate::parser::parse; 
 
 
 fn main() {
-    // parse the arguments given from the command line: the input file and the output file
-    let (input_file, output_file) = read_args();
+    // parse the arguments given from the command line: what to do with which input file,
+    // whether types are only checked at run time, and how much to optimise
+    let (action, dynamic, optimization) = read_args();
+    let input_file = match &action {
+        Action::Compile(input_file, ..) | Action::Run(input_file) => input_file.clone(),
+        Action::Repl => {
+            repl::repl(dynamic, optimization);
+            return;
+        },
+    };
+
+    // a program that is run prints nothing but its own output
+    let progress = |message: &str| {
+        if let Action::Compile(..) = action {
+            println!("{}", message);
+        }
+    };
 
     let contents = fs::read_to_string(&input_file);
     if let Err(contents) = contents {
@@ -29,15 +49,42 @@ fn main() {
         }
     }
 
-    println!("Parsing program {}...", &input_file);
+    progress(&format!("Parsing program {}...", &input_file));
 
     // contents was verified to be String above. this is safe.
     let text = contents.unwrap();
 
     // now we use the parser on the text
-    let ast = parse(&text);
+    let ast = parse(&text).unwrap_or_else(|error| report(error, &text, &input_file));
+
+    // the program must be well typed before it is compiled, unless it is dynamically typed:
+    // values of the wrong type then abort the program when they are used
+    if !dynamic {
+        progress("Checking types ...");
+        if let Err(error) = check(&ast) {
+            report(error, &text, &input_file);
+        }
+    }
 
     // now we compile
-    println!("Compiling ...");
-    compile(ast, &output_file);
+    progress("Compiling ...");
+    match action {
+        Action::Compile(_, output_file, emit, platform) => {
+            if let Err(error) = compile(ast, &output_file, emit, &platform, optimization) {
+                report(error, &text, &input_file);
+            }
+        },
+        // the program's value is its exit code, as for a compiled program
+        Action::Run(_) => match run(ast, &input_file, optimization) {
+            Ok(code) => process::exit(code),
+            Err(error) => report(error, &text, &input_file),
+        },
+        Action::Repl => unreachable!("The REPL has no input file."),
+    }
+}
+
+/// Reports an error in the program to the user, and exits.
+fn report(error: CodyError, text: &str, input_file: &str) -> ! {
+    eprint!("{}", error.render(text, input_file));
+    process::exit(1);
 }
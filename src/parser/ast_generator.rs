@@ -1,46 +1,70 @@
 //! This module is responsible for generating the AST from the tokens.
 
+use crate::error::{CodyError, ErrorCode, Span};
 use crate::parser::token_types::AtomBinary;
 use crate::parser::token_types::Token::{self, *};
 use crate::parser::node_types::ExpressionAST::{self, *};
+use crate::parser::node_types::{FieldType, ForeignType, Variant};
 
 /// Generates the AST from the token stream.
-pub fn ast_generate(tokens: &Vec<Token>) -> ExpressionAST {
+pub fn ast_generate(tokens: &[(Token, Span)]) -> Result<ExpressionAST, CodyError> {
     // we treat the token stream as a stack, reversing it to pop from the front
-    let mut remaining_tokens = tokens.clone();
+    let mut remaining_tokens = tokens.to_vec();
     remaining_tokens.reverse();
 
-    parse(&mut remaining_tokens)
-}
+    let ast = parse(&mut remaining_tokens)?;
 
-/// Parses the token stream into an AST.
-fn parse(tokens: &mut Vec<Token>) -> ExpressionAST {
-    match tokens.pop() {
-        Some(token) => match token {
-            // if the EOF is reached, return the NoneExpr.
-            // if it's in an internal recursive parse call the
-            // outer parse call will throw an error anyway.
-            EOF => NoneExpr,
-            LeftPar => parse_grouping(tokens),
-            LeftBkt => parse_pair(tokens), 
-            // Grave => parse_quote(tokens, Grave),
-            // Quote => parse_quote(tokens, Quote),
-            Integer(i) => IntegerExpr(i),
-            Identifier(s) => VariableExpr(s),
-
-            // everything else met at this level is an error
-            _ => panic!("Unexpected token: {:?}", token),
+    // the whole program must have been consumed
+    match remaining_tokens.pop() {
+        Some((EOF, _)) | None => Ok(ast),
+        Some((token, span)) => Err(unexpected_token(token, span)),
+    }
+}
 
+pub(super) fn unexpected_token(token: Token, span: Span) -> CodyError {
+    CodyError::new(ErrorCode::UnexpectedToken, format!("Unexpected token: {:?}", token), span)
+}
 
+/// Pops the next token from the token stream.
+/// The EOF token is never consumed, so that every parse past it reports where the program ended.
+fn next_token(tokens: &mut Vec<(Token, Span)>) -> Result<(Token, Span), CodyError> {
+    match tokens.pop() {
+        Some((EOF, span)) => {
+            tokens.push((EOF, span));
+            Err(CodyError::new(ErrorCode::UnexpectedEnd, "Unexpected end of program.", span))
         },
-        None => panic!("Unexpected end of token stream."),
+        Some(token) => Ok(token),
+        None => Err(CodyError::new(ErrorCode::UnexpectedEnd, "Unexpected end of program.", Span::default())),
+    }
+}
+
+/// Parses the token stream into an AST.
+fn parse(tokens: &mut Vec<(Token, Span)>) -> Result<ExpressionAST, CodyError> {
+    let (token, span) = next_token(tokens)?;
+    match token {
+        LeftPar => parse_grouping(tokens, span),
+        LeftBkt => parse_pair(tokens),
+        Grave => parse_datum(tokens, true),
+        Quote => parse_datum(tokens, false),
+        Integer(i) => Ok(IntegerExpr(i)),
+        Bits(value, bit_type) => Ok(BitsExpr(value, bit_type)),
+        Float(f) => Ok(FloatExpr(f)),
+        Bool(b) => Ok(BoolExpr(b)),
+        Char(c) => Ok(CharExpr(c)),
+        Str(s) => Ok(StringExpr(s)),
+        Identifier(s) => Ok(VariableExpr(s, span)),
+        // operators outside of the head of a grouping are function values
+        AtomicOp(op) => Ok(OperatorExpr(op)),
+
+        // everything else met at this level is an error
+        _ => Err(unexpected_token(token, span)),
     }
 }
 
-fn parse_grouping(tokens: &mut Vec<Token>) -> ExpressionAST {
-    let curr_token = tokens.pop().expect("Unexpected end of token stream.");
+fn parse_grouping(tokens: &mut Vec<(Token, Span)>, start: Span) -> Result<ExpressionAST, CodyError> {
+    let (curr_token, span) = next_token(tokens)?;
     match curr_token {
-        RightPar => NoneExpr,
+        RightPar => Ok(NoneExpr),
 
         // function objects
         Function => parse_function(tokens),
@@ -52,192 +76,400 @@ fn parse_grouping(tokens: &mut Vec<Token>) -> ExpressionAST {
         Define => parse_definition(tokens),
 
         // conditionals
-        If => parse_conditional(tokens),
+        If => parse_conditional(tokens, start),
 
         // match case
-        Match => parse_match(tokens),
+        Match => parse_match(tokens, start),
+
+        // continuations
+        Cont => parse_continuation(tokens, start),
 
-        // // continuations
-        // Cont => parse_continuation(tokens),
+        // external functions
+        Extern => parse_extern(tokens),
 
-        // // external functions
-        // Extern => parse_extern(tokens),
+        // enums
+        Enum => parse_enum(tokens),
 
         // atomic binary operators
-        AtomicOp(op) => parse_atomic_binary(tokens, op),
+        AtomicOp(op) => parse_atomic_binary(tokens, op, start),
 
         // identifiers or inner groupings
         Identifier(_) | LeftPar => {
             // add the token back so that we can evaluate it with parse()
-            tokens.push(curr_token);
-            parse_call(tokens)
+            tokens.push((curr_token, span));
+            parse_call(tokens, start)
         },
 
         // everything else is an error
-        _ => panic!("Unexpected token: {:?}", curr_token),
+        _ => Err(unexpected_token(curr_token, span)),
     }
 }
 
-fn close_grouping(tokens: &mut Vec<Token>, final_expression: ExpressionAST) -> ExpressionAST{
-    match tokens.pop() {
-        Some(token) => match token {
-            RightPar => final_expression,
-            _ => panic!("Unexpected token: {:?}", token),
-        },
-        None => panic!("Unexpected end of token stream."),
+/// The span of a grouping from its opening parenthesis, up to the closing parenthesis that is
+/// the next token once the grouping has been parsed.
+fn grouping_span(tokens: &[(Token, Span)], start: Span) -> Span {
+    let end = tokens.last().map_or(start.end, |(_, span)| span.end);
+    Span::new(start.start, end, start.line, start.column)
+}
+
+fn close_grouping<T>(tokens: &mut Vec<(Token, Span)>, final_expression: T) -> Result<T, CodyError> {
+    let (token, span) = next_token(tokens)?;
+    match token {
+        RightPar => Ok(final_expression),
+        _ => Err(unexpected_token(token, span)),
     }
 }
 
-fn parse_function(tokens: &mut Vec<Token>) -> ExpressionAST {
+fn parse_function(tokens: &mut Vec<(Token, Span)>) -> Result<ExpressionAST, CodyError> {
     let mut parameters: Vec<ExpressionAST> = Vec::new();
 
     // parse the parameter bracket
-    let mut curr_token = tokens.pop().expect("Unexpected end of token stream.");
+    let (curr_token, span) = next_token(tokens)?;
     match curr_token {
         LeftPar => (),
-        _ => panic!("Unexpected token: {:?}", curr_token),
+        _ => return Err(unexpected_token(curr_token, span)),
     }
 
     // parse the parameters
     loop {
-        curr_token = tokens.pop().expect("Unexpected end of token stream.");
+        let (curr_token, span) = next_token(tokens)?;
         match curr_token {
             RightPar => break,
             Identifier(s) => {
-                parameters.push(VariableExpr(s));
+                parameters.push(VariableExpr(s, span));
             },
-            _ => panic!("Unexpected token: {:?}", curr_token),
+            _ => return Err(unexpected_token(curr_token, span)),
         }
     }
 
     // parse the expression
-    let function_expression = parse(tokens);
+    let function_expression = parse(tokens)?;
 
     let new_function = FunctionExpr(parameters, Box::new(function_expression));
 
     close_grouping(tokens, new_function)
 }
 
-fn parse_sequence(tokens: &mut Vec<Token>) -> ExpressionAST {
+fn parse_sequence(tokens: &mut Vec<(Token, Span)>) -> Result<ExpressionAST, CodyError> {
     let mut expressions: Vec<ExpressionAST> = Vec::new();
     // parse the expressions
     loop {
-        let curr_token = tokens.pop().expect("Unexpected end of token stream.");
+        let (curr_token, span) = next_token(tokens)?;
         match curr_token {
             RightPar => break,
             _ => {
                 // add the token back so that we can evaluate it with parse()
-                tokens.push(curr_token);
-                
-                expressions.push(parse(tokens));
+                tokens.push((curr_token, span));
+
+                expressions.push(parse(tokens)?);
             },
         }
     }
-    SeqExpr(expressions)
+    Ok(SeqExpr(expressions))
 }
 
-fn parse_definition(tokens: &mut Vec<Token>) -> ExpressionAST {
-    let identifier = tokens.pop().expect("Unexpected end of token stream.");
+fn parse_definition(tokens: &mut Vec<(Token, Span)>) -> Result<ExpressionAST, CodyError> {
+    let (identifier, span) = next_token(tokens)?;
     let definition_node = match identifier {
-        Identifier(s) => DefineExpr(Box::new(VariableExpr(s)), Box::new(parse(tokens))),
-        _ => panic!("Unexpected token: {:?}", identifier),
+        Identifier(s) => DefineExpr(Box::new(VariableExpr(s, span)), Box::new(parse(tokens)?)),
+        _ => return Err(unexpected_token(identifier, span)),
     };
 
     close_grouping(tokens, definition_node)
 }
 
-fn parse_conditional(tokens: &mut Vec<Token>) -> ExpressionAST {
-    let predicate = parse(tokens);
-    let con = parse(tokens);
-    let alt = parse(tokens);
+fn parse_conditional(tokens: &mut Vec<(Token, Span)>, start: Span) -> Result<ExpressionAST, CodyError> {
+    let predicate = parse(tokens)?;
+    let con = parse(tokens)?;
+    let alt = parse(tokens)?;
 
-    close_grouping(tokens, IfExpr(Box::new(predicate), Box::new(con), Box::new(alt)))
+    let span = grouping_span(tokens, start);
+    close_grouping(tokens, IfExpr(Box::new(predicate), Box::new(con), Box::new(alt), span))
 }
 
-fn parse_match(tokens: &mut Vec<Token>) -> ExpressionAST {
-    let expression = parse(tokens);
+fn parse_match(tokens: &mut Vec<(Token, Span)>, start: Span) -> Result<ExpressionAST, CodyError> {
+    let expression = parse(tokens)?;
     let mut match_arms: Vec<ExpressionAST> = Vec::new();
-    
+
     // parse the match arms
     loop {
-        let mut curr_token = tokens.pop().expect("Unexpected end of token stream.");
+        let (curr_token, span) = next_token(tokens)?;
         match curr_token {
-            RightPar => break,
+            RightPar => return Ok(MatchExpr(Box::new(expression), match_arms, Span::new(start.start, span.end, start.line, start.column))),
             Pipe => {
                 let mut patterns: Vec<ExpressionAST> = Vec::new();
+                let mut destructuring: Option<Span> = None;
                 loop {
-                    curr_token = tokens.pop().expect("Unexpected end of token stream.");
+                    let (curr_token, span) = next_token(tokens)?;
                     match curr_token {
                         Arrow => break,
                         Integer(i) => patterns.push(IntegerExpr(i)),
+                        Bool(b) => patterns.push(BoolExpr(b)),
+                        Char(c) => patterns.push(CharExpr(c)),
                         // allowed for the catch-all case
-                        Identifier(s) => patterns.push(VariableExpr(s)),
-                        // we disallow matching on non-atomic data types
-                        _ => panic!("Unexpected token: {:?}", curr_token),
+                        Identifier(s) => patterns.push(VariableExpr(s, span)),
+                        // variants of enums, binding their fields
+                        LeftPar => {
+                            let pattern = parse_variant_pattern(tokens)?;
+                            if matches!(&pattern, VariantExpr(_, fields) if !fields.is_empty()) {
+                                destructuring = Some(span);
+                            }
+                            patterns.push(pattern);
+                        },
+                        // we disallow matching on other non-atomic data types
+                        _ => return Err(unexpected_token(curr_token, span)),
                     }
                 }
-                let match_expression = parse(tokens);
+                // the fields of a variant are only bound when the arm is reached through it
+                if let Some(span) = destructuring.filter(|_| patterns.len() > 1) {
+                    return Err(CodyError::new(ErrorCode::InvalidPattern, "A pattern binding the fields of a variant must be the only pattern of its arm.", span));
+                }
+                let match_expression = parse(tokens)?;
                 match_arms.push(MatchArmExpr(patterns, Box::new(match_expression)));
             },
-            _ => panic!("Unexpected token: {:?}", curr_token),
+            _ => return Err(unexpected_token(curr_token, span)),
         }
     }
-    MatchExpr(Box::new(expression), match_arms)
 }
 
-// fn parse_continuation(tokens: &mut Vec<Token>) -> ExpressionAST {
-//     let continuation_expression = parse(tokens);
+/// Parses a variant pattern after its opening parenthesis: the variant followed by the names
+/// bound to its fields.
+fn parse_variant_pattern(tokens: &mut Vec<(Token, Span)>) -> Result<ExpressionAST, CodyError> {
+    let (curr_token, span) = next_token(tokens)?;
+    let variant = match curr_token {
+        Identifier(s) => VariableExpr(s, span),
+        _ => return Err(unexpected_token(curr_token, span)),
+    };
+    let mut fields: Vec<ExpressionAST> = Vec::new();
+    loop {
+        let (curr_token, span) = next_token(tokens)?;
+        match curr_token {
+            RightPar => return Ok(VariantExpr(Box::new(variant), fields)),
+            Identifier(s) => fields.push(VariableExpr(s, span)),
+            _ => return Err(unexpected_token(curr_token, span)),
+        }
+    }
+}
 
-//     close_grouping(tokens, ContExpr(Box::new(continuation_expression)))
-// }
+fn parse_continuation(tokens: &mut Vec<(Token, Span)>, start: Span) -> Result<ExpressionAST, CodyError> {
+    let continuation_expression = parse(tokens)?;
 
-// fn parse_extern(tokens: &mut Vec<Token>) -> ExpressionAST {
-//     let identifier = tokens.pop().expect("Unexpected end of token stream.");
-//     let extern_node = match identifier {
-//         Identifier(s) => ExternExpr(Box::new(VariableExpr(s))),
-//         _ => panic!("Unexpected token: {:?}", identifier),
-//     };
+    let span = grouping_span(tokens, start);
+    close_grouping(tokens, ContExpr(Box::new(continuation_expression), span))
+}
 
-//     close_grouping(tokens, extern_node)
-// }
+fn parse_extern(tokens: &mut Vec<(Token, Span)>) -> Result<ExpressionAST, CodyError> {
+    let (identifier, span) = next_token(tokens)?;
+    let name = match identifier {
+        Identifier(s) => VariableExpr(s, span),
+        _ => return Err(unexpected_token(identifier, span)),
+    };
+
+    // parse the parameter types
+    let (curr_token, span) = next_token(tokens)?;
+    match curr_token {
+        LeftPar => (),
+        _ => return Err(unexpected_token(curr_token, span)),
+    }
+    let mut parameter_types: Vec<ForeignType> = Vec::new();
+    loop {
+        let (curr_token, span) = next_token(tokens)?;
+        match curr_token {
+            RightPar => break,
+            Identifier(s) => parameter_types.push(parse_foreign_type(&s, span, false)?),
+            _ => return Err(unexpected_token(curr_token, span)),
+        }
+    }
+
+    // parse the return type
+    let (curr_token, span) = next_token(tokens)?;
+    let return_type = match curr_token {
+        Identifier(s) => parse_foreign_type(&s, span, true)?,
+        _ => return Err(unexpected_token(curr_token, span)),
+    };
 
-fn parse_atomic_binary(tokens: &mut Vec<Token>, op: AtomBinary) -> ExpressionAST {
-    let left = parse(tokens);
-    let right = parse(tokens);
-    close_grouping(tokens, AtomBinExpr(op, Box::new(left), Box::new(right)))
+    close_grouping(tokens, ExternExpr(Box::new(name), parameter_types, return_type))
 }
 
-fn parse_call(tokens: &mut Vec<Token>) -> ExpressionAST {
+fn parse_enum(tokens: &mut Vec<(Token, Span)>) -> Result<ExpressionAST, CodyError> {
+    let (identifier, span) = next_token(tokens)?;
+    let name = match identifier {
+        Identifier(s) => VariableExpr(s, span),
+        _ => return Err(unexpected_token(identifier, span)),
+    };
+
+    // parse the variants, each a parenthesized name followed by the types of its fields
+    let mut variants: Vec<Variant> = Vec::new();
+    loop {
+        let (curr_token, span) = next_token(tokens)?;
+        match curr_token {
+            RightPar => return Ok(EnumExpr(Box::new(name), variants)),
+            LeftPar => (),
+            _ => return Err(unexpected_token(curr_token, span)),
+        }
+        let (curr_token, span) = next_token(tokens)?;
+        let variant_name = match curr_token {
+            Identifier(s) => s,
+            _ => return Err(unexpected_token(curr_token, span)),
+        };
+        let mut fields: Vec<FieldType> = Vec::new();
+        loop {
+            let (curr_token, field_span) = next_token(tokens)?;
+            match curr_token {
+                RightPar => break,
+                _ => {
+                    tokens.push((curr_token, field_span));
+                    fields.push(parse_field_type(tokens)?);
+                },
+            }
+        }
+        variants.push(Variant { name: variant_name, span, fields });
+    }
+}
+
+/// Parses the type of a field: a type name, or `(list type)`.
+fn parse_field_type(tokens: &mut Vec<(Token, Span)>) -> Result<FieldType, CodyError> {
+    let (curr_token, span) = next_token(tokens)?;
+    match curr_token {
+        Identifier(s) => Ok(FieldType::Named(s, span)),
+        LeftPar => {
+            let (curr_token, span) = next_token(tokens)?;
+            match curr_token {
+                Identifier(s) if s == "list" => (),
+                _ => return Err(unexpected_token(curr_token, span)),
+            }
+            let element = parse_field_type(tokens)?;
+            close_grouping(tokens, FieldType::List(Box::new(element)))
+        },
+        _ => Err(unexpected_token(curr_token, span)),
+    }
+}
+
+fn parse_foreign_type(name: &str, span: Span, is_return_type: bool) -> Result<ForeignType, CodyError> {
+    match ForeignType::from_name(name) {
+        Some(ForeignType::Void) if !is_return_type => Err(CodyError::new(ErrorCode::UnknownType, "void can only be the return type of an external function.", span)),
+        Some(foreign_type) => Ok(foreign_type),
+        None => Err(CodyError::new(ErrorCode::UnknownType, format!("Unknown foreign type: {}", name), span)),
+    }
+}
+
+fn parse_atomic_binary(tokens: &mut Vec<(Token, Span)>, op: AtomBinary, start: Span) -> Result<ExpressionAST, CodyError> {
+    let left = parse(tokens)?;
+    let right = parse(tokens)?;
+    let span = grouping_span(tokens, start);
+    close_grouping(tokens, AtomBinExpr(op, Box::new(left), Box::new(right), span))
+}
+
+fn parse_call(tokens: &mut Vec<(Token, Span)>, start: Span) -> Result<ExpressionAST, CodyError> {
     let mut arguments: Vec<ExpressionAST> = Vec::new();
-    let function = parse(tokens);
+    let function = parse(tokens)?;
 
     // parse the arguments
     loop {
-        let curr_token = tokens.pop().expect("Unexpected end of token stream.");
+        let (curr_token, span) = next_token(tokens)?;
         match curr_token {
-            RightPar => break,
+            RightPar => return Ok(CallExpr(Box::new(function), arguments, Span::new(start.start, span.end, start.line, start.column))),
             _ => {
                 // add the token back so that we can evaluate it with parse()
-                tokens.push(curr_token);
-                
-                arguments.push(parse(tokens));
-            }, 
+                tokens.push((curr_token, span));
+
+                arguments.push(parse(tokens)?);
+            },
         }
     }
+}
 
-    CallExpr(Box::new(function), arguments)
+/// Parses a quoted datum into the expression building it: lists and pairs become pairs, and
+/// identifiers and keywords become symbols. In a quasiquoted datum, `@expr` is replaced by the
+/// value of the expression.
+fn parse_datum(tokens: &mut Vec<(Token, Span)>, quasiquoted: bool) -> Result<ExpressionAST, CodyError> {
+    let (token, span) = next_token(tokens)?;
+    match token {
+        Integer(i) => Ok(IntegerExpr(i)),
+        Bits(value, bit_type) => Ok(BitsExpr(value, bit_type)),
+        Float(f) => Ok(FloatExpr(f)),
+        Bool(b) => Ok(BoolExpr(b)),
+        Char(c) => Ok(CharExpr(c)),
+        Str(s) => Ok(StringExpr(s)),
+        LeftPar => parse_datum_list(tokens, quasiquoted),
+        LeftBkt => {
+            let head = parse_datum(tokens, quasiquoted)?;
+            let (token, span) = next_token(tokens)?;
+            match token {
+                Dot => (),
+                _ => return Err(unexpected_token(token, span)),
+            }
+            let tail = parse_datum(tokens, quasiquoted)?;
+            let (token, span) = next_token(tokens)?;
+            match token {
+                RightBkt => Ok(PairExpr(Box::new(head), Box::new(tail))),
+                _ => Err(unexpected_token(token, span)),
+            }
+        },
+        At if quasiquoted => parse(tokens),
+        _ => match symbol_name(&token) {
+            Some(name) => Ok(SymbolExpr(name)),
+            None => Err(unexpected_token(token, span)),
+        },
+    }
 }
 
-fn parse_pair(tokens: &mut Vec<Token>) -> ExpressionAST {
-    let head = parse(tokens);
-    match tokens.pop() {
-        Some(token) => match token {
-            Dot => {
-                let tail = parse(tokens);
-                PairExpr(Box::new(head), Box::new(tail))
-            },
-            _ => panic!("Unexpected token: {:?}", token),
+/// Parses the rest of a quoted list, up to its closing parenthesis.
+fn parse_datum_list(tokens: &mut Vec<(Token, Span)>, quasiquoted: bool) -> Result<ExpressionAST, CodyError> {
+    let (token, span) = next_token(tokens)?;
+    match token {
+        RightPar => Ok(NoneExpr),
+        // a dotted list ends with the datum after the dot instead of ()
+        Dot => {
+            let tail = parse_datum(tokens, quasiquoted)?;
+            close_grouping(tokens, tail)
+        },
+        _ => {
+            // add the token back so that we can evaluate it with parse_datum()
+            tokens.push((token, span));
+
+            let head = parse_datum(tokens, quasiquoted)?;
+            let tail = parse_datum_list(tokens, quasiquoted)?;
+            Ok(PairExpr(Box::new(head), Box::new(tail)))
         },
-        None => panic!("Unexpected end of token stream."),
     }
-}
\ No newline at end of file
+}
+
+/// The name of the symbol a token stands for when quoted.
+fn symbol_name(token: &Token) -> Option<String> {
+    let name = match token {
+        Identifier(s) => return Some(s.clone()),
+        Function => "fn",
+        Seq => "seq",
+        Define => "define",
+        If => "if",
+        Match => "match",
+        Cont => "cont",
+        Extern => "extern",
+        Enum => "enum",
+        DefineMacro => "define-macro",
+        SyntaxRules => "syntax-rules",
+        Ellipsis => "...",
+        Pipe => "|",
+        Arrow => "->",
+        AtomicOp(op) => op.symbol(),
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+fn parse_pair(tokens: &mut Vec<(Token, Span)>) -> Result<ExpressionAST, CodyError> {
+    let head = parse(tokens)?;
+    let (token, span) = next_token(tokens)?;
+    match token {
+        Dot => {
+            let tail = parse(tokens)?;
+            let (token, span) = next_token(tokens)?;
+            match token {
+                RightBkt => Ok(PairExpr(Box::new(head), Box::new(tail))),
+                _ => Err(unexpected_token(token, span)),
+            }
+        },
+        _ => Err(unexpected_token(token, span)),
+    }
+}
@@ -1,121 +1,454 @@
-//! SUPER simple lexer.
-//! Lexes the program into tokens that the parser can work on.
-
-use crate::parser::token_types::{Token, AtomBinary};
-
-// #[macro_export]
-// macro_rules! remove_from_program {
-//     ( $prog:expr ,$( $x:expr ),* ) => {
-//         {
-//             let mut temp = $prog.clone()
-//             $(
-//                 .replace($x, format!(" {} ", &$x));
-//             )*
-//             temp
-//         }
-//     };
-// }
-/// Lexes a program string into an array of Tokens.
-pub fn lex(program: &str) -> Vec<Token> {
-    // let formatted_program = remove_from_program!(program, "(", ")", "[", "]", ".", "'", "`", "@", ";", "|", "->", "$+", "$-", "$*", "$/", "$=", "$<", "$!", "$&", "$|");
-    let formatted_program = program
-    .replace("(", " ( ")
-    .replace(")", " ) ")
-    .replace("[", " [ ")
-    .replace("]", " ] ")
-    .replace(".", " . ")
-    .replace("'", " ' ")
-    .replace("`", " ` ")
-    .replace("@", " @ ")
-    .replace(";", " ; ")
-    .replace("|", " | ")
-    .replace("->", " -> ")
-    
-    // atomic binary operators that we will use to bulld the basic library
-    .replace("+", " $+ ")
-    .replace("-", " $- ")
-    .replace("*", " $* ")
-    .replace("/", " $/ ")
-    .replace("=", " $= ")
-    .replace("<", " $< ")
-    .replace("!", " $! ")
-    .replace("&", " $& ")
-    .replace("|", " $| ");
-    
-    let mut tokens: Vec<Token> = Vec::new();
-    // the entire program is treated as a sequence expression, 
+//! Character-level scanner.
+//! Lexes the program into tokens that the parser can work on, recording the span of source
+//! each token was read from.
+
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::error::{CodyError, ErrorCode, Span};
+use crate::parser::token_types::{AtomBinary, BitType, Token};
+
+/// Lexes a program string into an array of Tokens, each with the span of source it was read from.
+pub fn lex(program: &str) -> Result<Vec<(Token, Span)>, CodyError> {
+    let mut scanner = Scanner::new(program);
+    let mut tokens: Vec<(Token, Span)> = Vec::new();
+    // the entire program is treated as a sequence expression,
     // hence we prepare the token stream to be parsed as such
-    tokens.push(Token::LeftPar);
-    tokens.push(Token::Seq);
-    for curr_line in formatted_program.lines() {
-        for curr_word in curr_line.split_whitespace() {
-            match curr_word {
-                // comments
-                ";" => break,
-
-                // parantheses
-                "(" => tokens.push(Token::LeftPar),
-                ")" => tokens.push(Token::RightPar),
-
-                // match case syntaxfrom
-                "|" => tokens.push(Token::Pipe),
-                "->" => tokens.push(Token::Arrow),
-                "match" => tokens.push(Token::Match),
-
-                // sequence expressions
-                "seq" => tokens.push(Token::Seq),
-
-                // definition syntax
-                "define" => tokens.push(Token::Define),
-
-                // functions
-                "fn" => tokens.push(Token::Function),
-                
-                // conditionals
-                "if" => tokens.push(Token::If),
-                
-                // continuations
-                "cont" => tokens.push(Token::Cont),
-
-                // pair syntax
-                "[" => tokens.push(Token::LeftBkt),
-                "]" => tokens.push(Token::RightBkt),
-                "." => tokens.push(Token::Dot),
-
-                // quote syntax
-                "`" => tokens.push(Token::Grave),
-                "'" => tokens.push(Token::Quote),
-                "@" => tokens.push(Token::At),
-
-                // external functions
-                "extern" => tokens.push(Token::Extern),
-
-                // atomic binary operators
-                "$+" => tokens.push(Token::AtomicOp(AtomBinary::Add)),
-                "$-" => tokens.push(Token::AtomicOp(AtomBinary::Sub)),
-                "$*" => tokens.push(Token::AtomicOp(AtomBinary::Mul)),
-                "$/" => tokens.push(Token::AtomicOp(AtomBinary::Div)),
-                "$=" => tokens.push(Token::AtomicOp(AtomBinary::Eq)),
-                "$<" => tokens.push(Token::AtomicOp(AtomBinary::Lt)),
-                "$!" => tokens.push(Token::AtomicOp(AtomBinary::Not)),
-                "$&" => tokens.push(Token::AtomicOp(AtomBinary::And)),
-                "$|" => tokens.push(Token::AtomicOp(AtomBinary::Or)),
-
-                // integers and identifiers
-                rest => {
-                    if rest.parse::<i32>().is_ok() {
-                        tokens.push(Token::Integer(rest.parse::<i32>().unwrap()));
-                    } else {
-                        tokens.push(Token::Identifier(curr_word.to_string()));
-                    }
+    let start_span = Span::new(0, 0, 1, 1);
+    tokens.push((Token::LeftPar, start_span));
+    tokens.push((Token::Seq, start_span));
+
+    while let Some(token) = scanner.next_token()? {
+        tokens.push(token);
+    }
+
+    // add the ending of the sequence expression
+    let end_span = scanner.span_from(program.len(), scanner.line, scanner.column);
+    tokens.push((Token::RightPar, end_span));
+
+    // add the last token
+    tokens.push((Token::EOF, end_span));
+    Ok(tokens)
+}
+
+/// Characters that may appear in an identifier after its first character,
+/// which allows kebab-case identifiers such as `plus-or-minus`.
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '?' | '!')
+}
+
+fn atomic_op(c: char) -> Option<AtomBinary> {
+    match c {
+        '+' => Some(AtomBinary::Add),
+        '-' => Some(AtomBinary::Sub),
+        '*' => Some(AtomBinary::Mul),
+        '/' => Some(AtomBinary::Div),
+        '=' => Some(AtomBinary::Eq),
+        '<' => Some(AtomBinary::Lt),
+        '!' => Some(AtomBinary::Not),
+        '&' => Some(AtomBinary::And),
+        '|' => Some(AtomBinary::Or),
+        _ => None,
+    }
+}
+
+struct Scanner<'p> {
+    program: &'p str,
+    chars: Peekable<CharIndices<'p>>,
+    // position of the next character
+    line: usize,
+    column: usize,
+}
+
+impl<'p> Scanner<'p> {
+    fn new(program: &'p str) -> Scanner<'p> {
+        Scanner {
+            program,
+            chars: program.char_indices().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    /// The byte offset of the next character.
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map(|(i, _)| *i).unwrap_or(self.program.len())
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /// The span from a starting position up to the next character.
+    fn span_from(&mut self, start: usize, line: usize, column: usize) -> Span {
+        Span::new(start, self.offset(), line, column)
+    }
+
+    /// Skips whitespace and `;` comments, which run to the end of the line.
+    fn skip_trivia(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == ';' {
+                while !matches!(self.peek(), Some('\n') | None) {
+                    self.advance();
+                }
+            } else if c.is_whitespace() {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Scans the next token, returning `None` at the end of the program.
+    fn next_token(&mut self) -> Result<Option<(Token, Span)>, CodyError> {
+        self.skip_trivia();
+        let (start, line, column) = (self.offset(), self.line, self.column);
+        let c = match self.advance() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        let token = match c {
+            // parantheses
+            '(' => Token::LeftPar,
+            ')' => Token::RightPar,
+
+            // pair syntax
+            '[' => Token::LeftBkt,
+            ']' => Token::RightBkt,
+            '.' if self.peek() != Some('.') => Token::Dot,
+
+            // macro syntax, repeating the pattern before it
+            '.' => {
+                self.advance();
+                match self.advance() {
+                    Some('.') => Token::Ellipsis,
+                    _ => return Err(self.unexpected_character('.', start, line, column)),
+                }
+            },
+
+            // quote syntax
+            '`' => Token::Grave,
+            '\'' => Token::Quote,
+            '@' => Token::At,
+
+            // match case syntax
+            '|' => Token::Pipe,
+            '-' if self.peek() == Some('>') => {
+                self.advance();
+                Token::Arrow
+            },
+
+            // negative numbers
+            '-' if self.peek().is_some_and(|c| c.is_ascii_digit()) => self.scan_number(start, line, column)?,
+
+            // atomic binary operators, written either bare or prefixed with $
+            '$' => match self.peek().and_then(atomic_op) {
+                Some(op) => {
+                    self.advance();
+                    Token::AtomicOp(self.scan_float_op(op))
                 },
+                None => return Err(self.unexpected_character('$', start, line, column)),
+            },
+            c if atomic_op(c).is_some() => Token::AtomicOp(self.scan_float_op(atomic_op(c).unwrap())),
+
+            // strings
+            '"' => self.scan_string(start, line, column)?,
+
+            // characters
+            '#' if self.peek() == Some('\\') => {
+                self.advance();
+                self.scan_character(start, line, column)?
+            },
+
+            // numbers
+            c if c.is_ascii_digit() => self.scan_number(start, line, column)?,
+
+            // keywords and identifiers
+            c if c.is_alphabetic() || c == '_' => {
+                while self.peek().is_some_and(is_identifier_char) {
+                    self.advance();
+                }
+                match &self.program[start..self.offset()] {
+                    // match case syntax
+                    "match" => Token::Match,
+
+                    // sequence expressions
+                    "seq" => Token::Seq,
+
+                    // definition syntax
+                    "define" => Token::Define,
+
+                    // functions
+                    "fn" => Token::Function,
+
+                    // enums
+                    "enum" => Token::Enum,
+
+                    // conditionals
+                    "if" => Token::If,
+
+                    // booleans
+                    "true" => Token::Bool(true),
+                    "false" => Token::Bool(false),
+
+                    // continuations
+                    "cont" => Token::Cont,
+
+                    // external functions
+                    "extern" => Token::Extern,
+
+                    // macros
+                    "define-macro" => Token::DefineMacro,
+                    "syntax-rules" => Token::SyntaxRules,
+
+                    identifier => Token::Identifier(identifier.to_string()),
+                }
+            },
+
+            c => return Err(self.unexpected_character(c, start, line, column)),
+        };
+        Ok(Some((token, self.span_from(start, line, column))))
+    }
+
+    /// The character after the next one.
+    fn peek_second(&mut self) -> Option<char> {
+        let offset = self.offset();
+        self.program[offset..].chars().nth(1)
+    }
+
+    fn skip_digits(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.advance();
+        }
+    }
+
+    /// Scans the `.` of the operator on floats following an operator on integers, if there is one.
+    fn scan_float_op(&mut self, op: AtomBinary) -> AtomBinary {
+        match op.on_floats() {
+            Some(float_op) if self.peek() == Some('.') => {
+                self.advance();
+                float_op
+            },
+            _ => op,
+        }
+    }
+
+    /// Scans the rest of a number literal whose first character has been consumed.
+    /// A number with a fraction, such as `1.5` or `2.0e-3`, is a float, and any other is an integer.
+    fn scan_number(&mut self, start: usize, line: usize, column: usize) -> Result<Token, CodyError> {
+        self.skip_digits();
+        // the dot of a pair, as in [1 . 2], is never followed by a digit
+        if self.peek() == Some('.') && self.peek_second().is_some_and(|c| c.is_ascii_digit()) {
+            self.advance();
+            self.skip_digits();
+            if matches!(self.peek(), Some('e' | 'E')) {
+                let exponent_digit = match self.peek_second() {
+                    Some('+' | '-') => {
+                        self.advance();
+                        self.peek_second()
+                    },
+                    c => c,
+                };
+                if !exponent_digit.is_some_and(|c| c.is_ascii_digit()) {
+                    return Err(CodyError::new(
+                        ErrorCode::InvalidLiteral,
+                        format!("Float literal {} has an exponent without digits.", &self.program[start..self.offset()]),
+                        self.span_from(start, line, column)
+                    ));
+                }
+                self.advance();
+                self.skip_digits();
             }
+            let literal = &self.program[start..self.offset()];
+            // every literal made of digits parses, although it may round to infinity
+            return Ok(Token::Float(literal.parse::<f64>().unwrap()));
+        }
+
+        // a bit-precise integer has its type written right after its digits, as in 255u8
+        if matches!(self.peek(), Some('u' | 'i')) && self.peek_second().is_some_and(|c| c.is_ascii_digit()) {
+            return self.scan_bit_suffix(start, line, column);
+        }
+
+        let literal = &self.program[start..self.offset()];
+        match literal.parse::<i32>() {
+            Ok(i) => Ok(Token::Integer(i)),
+            Err(_) => Err(CodyError::new(
+                ErrorCode::InvalidLiteral,
+                format!("Integer literal {} does not fit in 32 bits.", literal),
+                self.span_from(start, line, column)
+            )),
         }
     }
-    // add the ending of the sequence expression
-    tokens.push(Token::RightPar);
 
-    // add the last token
-    tokens.push(Token::EOF);
-    tokens
-}
\ No newline at end of file
+    /// Scans the type of a bit-precise integer literal, after its digits.
+    fn scan_bit_suffix(&mut self, start: usize, line: usize, column: usize) -> Result<Token, CodyError> {
+        let digits_end = self.offset();
+        self.advance();
+        self.skip_digits();
+        let literal = &self.program[start..self.offset()];
+        let bit_type = match BitType::from_name(&self.program[digits_end..self.offset()]) {
+            Some(bit_type) => bit_type,
+            None => return Err(CodyError::new(
+                ErrorCode::InvalidLiteral,
+                format!("Integer literal {} has a type that is not u or i followed by a width from 1 to 64.", literal),
+                self.span_from(start, line, column)
+            )),
+        };
+        match self.program[start..digits_end].parse::<i128>() {
+            // the value is kept extended to 64 bits, with its sign if the type is signed
+            Ok(value) if (bit_type.min()..=bit_type.max()).contains(&value) => Ok(Token::Bits(value as u64, bit_type)),
+            _ => Err(CodyError::new(
+                ErrorCode::InvalidLiteral,
+                format!("Integer literal {} does not fit in {}.", literal, bit_type),
+                self.span_from(start, line, column)
+            )),
+        }
+    }
+
+    /// Scans the rest of a string literal after its opening `"`, replacing each escape sequence
+    /// by the character it stands for.
+    fn scan_string(&mut self, start: usize, line: usize, column: usize) -> Result<Token, CodyError> {
+        let mut string = String::new();
+        loop {
+            let c = match self.advance() {
+                Some('"') => return Ok(Token::Str(string)),
+                Some('\\') => match self.advance() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('0') => '\0',
+                    Some('\\') => '\\',
+                    Some('"') => '"',
+                    Some(c) => return Err(CodyError::new(
+                        ErrorCode::InvalidLiteral,
+                        format!("Unknown escape sequence \\{} in string literal.", c),
+                        self.span_from(start, line, column)
+                    )),
+                    None => break,
+                },
+                Some(c) => c,
+                None => break,
+            };
+            string.push(c);
+        }
+        Err(CodyError::new(ErrorCode::UnterminatedString, "Unterminated string literal.", self.span_from(start, line, column)))
+    }
+
+    /// Scans the rest of a character literal after its `#\`: either a single character, as in
+    /// `#\a`, or the name of a whitespace character, as in `#\space`.
+    fn scan_character(&mut self, start: usize, line: usize, column: usize) -> Result<Token, CodyError> {
+        let c = match self.advance() {
+            Some(c) => c,
+            None => return Err(CodyError::new(ErrorCode::InvalidLiteral, "Character literal has no character.", self.span_from(start, line, column))),
+        };
+        if !c.is_alphabetic() || !self.peek().is_some_and(|c| c.is_alphabetic()) {
+            return Ok(Token::Char(c));
+        }
+        let name_start = self.offset() - c.len_utf8();
+        while self.peek().is_some_and(|c| c.is_alphabetic()) {
+            self.advance();
+        }
+        match &self.program[name_start..self.offset()] {
+            "space" => Ok(Token::Char(' ')),
+            "newline" => Ok(Token::Char('\n')),
+            "tab" => Ok(Token::Char('\t')),
+            name => Err(CodyError::new(
+                ErrorCode::InvalidLiteral,
+                format!("Unknown character name {}.", name),
+                self.span_from(start, line, column)
+            )),
+        }
+    }
+
+    fn unexpected_character(&mut self, c: char, start: usize, line: usize, column: usize) -> CodyError {
+        CodyError::new(ErrorCode::UnexpectedCharacter, format!("Unexpected character: {:?}", c), self.span_from(start, line, column))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tokens of a program, without the sequence wrapping it and the end of the program.
+    fn tokens(program: &str) -> Vec<(Token, Span)> {
+        let mut tokens = lex(program).expect("Failed to lex the program.");
+        tokens.truncate(tokens.len() - 2);
+        tokens.split_off(2)
+    }
+
+    fn error(program: &str) -> CodyError {
+        lex(program).expect_err("The program lexed without an error.")
+    }
+
+    #[test]
+    fn spans_count_bytes_and_columns_count_characters() {
+        assert_eq!(tokens("(define x\n  \"é\" 12)"), vec![
+            (Token::LeftPar, Span::new(0, 1, 1, 1)),
+            (Token::Define, Span::new(1, 7, 1, 2)),
+            (Token::Identifier("x".to_string()), Span::new(8, 9, 1, 9)),
+            (Token::Str("é".to_string()), Span::new(12, 16, 2, 3)),
+            (Token::Integer(12), Span::new(17, 19, 2, 7)),
+            (Token::RightPar, Span::new(19, 20, 2, 9)),
+        ]);
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        let tokens: Vec<Token> = tokens("; a comment\n1 ; another\n2").into_iter().map(|(token, _)| token).collect();
+        assert_eq!(tokens, vec![Token::Integer(1), Token::Integer(2)]);
+    }
+
+    #[test]
+    fn numbers_operators_and_pairs() {
+        let tokens: Vec<Token> = tokens("[-3 . 2.5e-1] $+. * 255u8 -3i8 #\\space ...").into_iter().map(|(token, _)| token).collect();
+        assert_eq!(tokens, vec![
+            Token::LeftBkt,
+            Token::Integer(-3),
+            Token::Dot,
+            Token::Float(0.25),
+            Token::RightBkt,
+            Token::AtomicOp(AtomBinary::FAdd),
+            Token::AtomicOp(AtomBinary::Mul),
+            Token::Bits(255, BitType { width: 8, signed: false }),
+            Token::Bits(-3i64 as u64, BitType { width: 8, signed: true }),
+            Token::Char(' '),
+            Token::Ellipsis,
+        ]);
+    }
+
+    #[test]
+    fn unterminated_string_is_reported_at_its_start() {
+        let error = error("(print\n  \"abc");
+        assert_eq!(error.code, ErrorCode::UnterminatedString);
+        assert_eq!((error.span.line, error.span.column), (2, 3));
+    }
+
+    #[test]
+    fn invalid_literals() {
+        assert_eq!(error("2147483648").code, ErrorCode::InvalidLiteral);
+        assert_eq!(error("256u8").code, ErrorCode::InvalidLiteral);
+        assert_eq!(error("1u65").code, ErrorCode::InvalidLiteral);
+        assert_eq!(error("1.5e").code, ErrorCode::InvalidLiteral);
+        assert_eq!(error("\"\\q\"").code, ErrorCode::InvalidLiteral);
+        assert_eq!(error("#\\nothing").code, ErrorCode::InvalidLiteral);
+    }
+
+    #[test]
+    fn unexpected_character_is_reported_where_it_is() {
+        let error = error("(é {)");
+        assert_eq!(error.code, ErrorCode::UnexpectedCharacter);
+        assert_eq!(error.span, Span::new(4, 5, 1, 4));
+    }
+}
@@ -0,0 +1,621 @@
+//! Macro expansion.
+//! Macros are defined with `define-macro` and `syntax-rules`, and are expanded on the tokens of
+//! the program before it is parsed, so that a macro can expand into any form of the language.
+//!
+//! Expansion is hygienic: every identifier a macro's template introduces is marked with the
+//! expansion it came from, and the ones bound by the expansion are renamed, so that they never
+//! capture identifiers written at the macro's use.
+//!
+//! Hygiene only goes that far. Expansion happens before the program is parsed, when there are no
+//! scopes to resolve an identifier in, so the free identifiers of a template keep their name and
+//! refer to whatever binds that name where the macro is used, not where it was defined: a macro
+//! whose template calls `car` calls the `car` of a `let` around its use.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::error::{CodyError, ErrorCode, Span};
+use crate::parser::ast_generator::unexpected_token;
+use crate::parser::lexer;
+use crate::parser::token_types::Token::{self, *};
+
+/// The macros every program can use.
+const LIBRARY: &str = include_str!("library.cdy");
+
+/// How many expansions may be nested in one another before a macro is taken to expand without end.
+const EXPANSION_LIMIT: usize = 1024;
+
+/// The expansion a token was introduced by, 0 for the tokens of the program.
+type Mark = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Delimiter {
+    Parenthesis,
+    Bracket,
+}
+
+/// The tokens of a program, grouped by their brackets.
+#[derive(Clone, Debug)]
+enum Syntax {
+    Atom(Token, Span, Mark),
+    /// A grouping, with the spans of its opening and closing brackets.
+    Group(Delimiter, Vec<Syntax>, Span, Span),
+}
+
+use Syntax::*;
+
+/// What a pattern variable matched.
+#[derive(Clone, Debug)]
+enum Binding {
+    One(Syntax),
+    /// The matches of a pattern followed by an ellipsis, one for each repetition.
+    Many(Vec<Binding>),
+}
+
+struct Macro {
+    literals: Vec<String>,
+    /// The patterns and templates of the rules, tried in order. A template is a sequence of
+    /// items, so that it can be a quoted datum.
+    rules: Vec<(Syntax, Vec<Syntax>)>,
+}
+
+/// Expands the macros in the token stream of a program.
+pub fn expand(tokens: &[(Token, Span)]) -> Result<Vec<(Token, Span)>, CodyError> {
+    let mut expander = Expander {
+        macros: HashMap::new(),
+        marks: 0,
+        depth: 0,
+    };
+    lexer::lex(LIBRARY)
+        .and_then(|library| read(&library))
+        .and_then(|library| expander.expand_items(library, false))
+        .expect("Failed to expand the macro library.");
+
+    let program = expander.expand_items(read(tokens)?, false)?;
+
+    let mut renamed: HashSet<(String, Mark)> = HashSet::new();
+    collect_binders(&program, &mut renamed);
+    let mut expanded_tokens: Vec<(Token, Span)> = Vec::new();
+    write(program, &renamed, &mut expanded_tokens);
+    Ok(expanded_tokens)
+}
+
+/// Groups the tokens by their brackets.
+fn read(tokens: &[(Token, Span)]) -> Result<Vec<Syntax>, CodyError> {
+    // the groups that are still open, with the items read before each of them
+    let mut open_groups: Vec<(Delimiter, Span, Vec<Syntax>)> = Vec::new();
+    let mut items: Vec<Syntax> = Vec::new();
+    for (token, span) in tokens {
+        match token {
+            LeftPar => open_groups.push((Delimiter::Parenthesis, *span, std::mem::take(&mut items))),
+            LeftBkt => open_groups.push((Delimiter::Bracket, *span, std::mem::take(&mut items))),
+            RightPar | RightBkt => {
+                let delimiter = if *token == RightPar { Delimiter::Parenthesis } else { Delimiter::Bracket };
+                match open_groups.pop() {
+                    Some((open_delimiter, open, outer_items)) if open_delimiter == delimiter => {
+                        let group = Group(delimiter, std::mem::replace(&mut items, outer_items), open, *span);
+                        items.push(group);
+                    },
+                    _ => return Err(unexpected_token(token.clone(), *span)),
+                }
+            },
+            EOF if !open_groups.is_empty() => {
+                return Err(CodyError::new(ErrorCode::UnexpectedEnd, "Unexpected end of program.", *span));
+            },
+            _ => items.push(Atom(token.clone(), *span, 0)),
+        }
+    }
+    Ok(items)
+}
+
+/// Turns the grouped tokens back into a token stream, renaming the identifiers bound by expansions.
+/// Quoted data is left as it is, as its identifiers are symbols.
+fn write(items: Vec<Syntax>, renamed: &HashSet<(String, Mark)>, tokens: &mut Vec<(Token, Span)>) {
+    let mut items = items.into_iter();
+    while let Some(item) = items.next() {
+        let quoted = matches!(item, Atom(Quote, ..) | Atom(Grave, ..));
+        write_item(item, Some(renamed), tokens);
+        if quoted {
+            if let Some(datum) = items.next() {
+                write_datum(datum, renamed, tokens);
+            }
+        }
+    }
+}
+
+/// Writes a quoted datum, renaming only in the values spliced into it.
+fn write_datum(datum: Syntax, renamed: &HashSet<(String, Mark)>, tokens: &mut Vec<(Token, Span)>) {
+    match datum {
+        Group(delimiter, items, open, close) => {
+            let (left, right) = brackets(delimiter);
+            tokens.push((left, open));
+            let mut items = items.into_iter();
+            while let Some(item) = items.next() {
+                let spliced = matches!(item, Atom(At, ..));
+                write_datum(item, renamed, tokens);
+                if spliced {
+                    if let Some(value) = items.next() {
+                        write(vec![value], renamed, tokens);
+                    }
+                }
+            }
+            tokens.push((right, close));
+        },
+        atom => write_item(atom, None, tokens),
+    }
+}
+
+fn write_item(item: Syntax, renamed: Option<&HashSet<(String, Mark)>>, tokens: &mut Vec<(Token, Span)>) {
+    match item {
+        Atom(Identifier(name), span, mark) if renamed.is_some_and(|renamed| renamed.contains(&(name.clone(), mark))) => {
+            tokens.push((Identifier(format!("{}%{}", name, mark)), span));
+        },
+        Atom(token, span, _) => tokens.push((token, span)),
+        Group(delimiter, items, open, close) => {
+            let (left, right) = brackets(delimiter);
+            tokens.push((left, open));
+            match renamed {
+                Some(renamed) => write(items, renamed, tokens),
+                None => items.into_iter().for_each(|item| write_item(item, None, tokens)),
+            }
+            tokens.push((right, close));
+        },
+    }
+}
+
+fn brackets(delimiter: Delimiter) -> (Token, Token) {
+    match delimiter {
+        Delimiter::Parenthesis => (LeftPar, RightPar),
+        Delimiter::Bracket => (LeftBkt, RightBkt),
+    }
+}
+
+/// Collects the identifiers introduced by expansions that are bound by the forms they expanded
+/// into: function parameters, definitions, external functions, variants of enums and match bindings.
+fn collect_binders(items: &[Syntax], binders: &mut HashSet<(String, Mark)>) {
+    for item in items {
+        if let Group(delimiter, items, ..) = item {
+            let bound: Vec<&Syntax> = match (delimiter, items.first()) {
+                (Delimiter::Parenthesis, Some(Atom(Function, ..))) => match items.get(1) {
+                    Some(Group(_, parameters, ..)) => parameters.iter().collect(),
+                    _ => Vec::new(),
+                },
+                (Delimiter::Parenthesis, Some(Atom(Define | Extern, ..))) => items.get(1).into_iter().collect(),
+                (Delimiter::Parenthesis, Some(Atom(Enum, ..))) => items.iter().skip(2)
+                    .filter_map(|variant| match variant {
+                        Group(_, variant, ..) => variant.first(),
+                        _ => None,
+                    })
+                    .collect(),
+                // the identifiers between a | and its -> are match bindings
+                (Delimiter::Parenthesis, Some(Atom(Match, ..))) => {
+                    let mut bound: Vec<&Syntax> = Vec::new();
+                    let mut in_patterns = false;
+                    for item in items {
+                        match item {
+                            Atom(Pipe, ..) => in_patterns = true,
+                            Atom(Arrow, ..) => in_patterns = false,
+                            // a variant pattern binds the identifiers after its variant
+                            Group(_, fields, ..) if in_patterns => bound.extend(fields.iter().skip(1)),
+                            item if in_patterns => bound.push(item),
+                            _ => (),
+                        }
+                    }
+                    bound
+                },
+                _ => Vec::new(),
+            };
+            for binder in bound {
+                if let Atom(Identifier(name), _, mark) = binder {
+                    if *mark != 0 {
+                        binders.insert((name.clone(), *mark));
+                    }
+                }
+            }
+            collect_binders(items, binders);
+        }
+    }
+}
+
+struct Expander {
+    macros: HashMap<String, Macro>,
+    /// The number of expansions so far, which marks the identifiers of the next one.
+    marks: Mark,
+    /// The number of expansions being expanded in one another.
+    depth: usize,
+}
+
+impl Expander {
+    /// Expands the items of a grouping. Quoted data is not expanded, except for the values
+    /// spliced into a quasiquoted datum.
+    fn expand_items(&mut self, items: Vec<Syntax>, quasiquoted: bool) -> Result<Vec<Syntax>, CodyError> {
+        let mut expanded: Vec<Syntax> = Vec::new();
+        let mut items = items.into_iter();
+        while let Some(item) = items.next() {
+            match item {
+                Atom(Quote, ..) if !quasiquoted => {
+                    expanded.push(item);
+                    expanded.extend(items.next());
+                },
+                Atom(Grave, ..) if !quasiquoted => {
+                    expanded.push(item);
+                    match items.next() {
+                        Some(Group(delimiter, datum, open, close)) => {
+                            expanded.push(Group(delimiter, self.expand_items(datum, true)?, open, close));
+                        },
+                        Some(datum) => {
+                            let spliced = matches!(datum, Atom(At, ..));
+                            expanded.push(datum);
+                            if spliced {
+                                expanded.extend(items.next().map(|value| self.expand(value)).transpose()?.into_iter().flatten());
+                            }
+                        },
+                        None => (),
+                    }
+                },
+                Atom(At, ..) if quasiquoted => {
+                    expanded.push(item);
+                    expanded.extend(items.next().map(|value| self.expand(value)).transpose()?.into_iter().flatten());
+                },
+                Group(delimiter, datum, open, close) if quasiquoted => {
+                    expanded.push(Group(delimiter, self.expand_items(datum, true)?, open, close));
+                },
+                item if quasiquoted => expanded.push(item),
+                item => expanded.extend(self.expand(item)?),
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Expands an item, into the items of the expansion if it is a use of a macro.
+    fn expand(&mut self, syntax: Syntax) -> Result<Vec<Syntax>, CodyError> {
+        match syntax {
+            Group(Delimiter::Parenthesis, items, open, close) => {
+                let span = Span::new(open.start, close.end, open.line, open.column);
+                let macro_name: Option<String> = match items.first() {
+                    Some(Atom(Identifier(name), ..)) if self.macros.contains_key(name) => Some(name.clone()),
+                    _ => None,
+                };
+                if matches!(items.first(), Some(Atom(DefineMacro, ..))) {
+                    // definitions are replaced by ()
+                    self.define(items, close)?;
+                    Ok(vec![Group(Delimiter::Parenthesis, Vec::new(), open, close)])
+                } else if let Some(name) = macro_name {
+                    if self.depth == EXPANSION_LIMIT {
+                        return Err(CodyError::new(ErrorCode::InvalidMacro, format!("Macro {} expands without end.", name), span));
+                    }
+                    let expansion = self.apply(&name, Group(Delimiter::Parenthesis, items, open, close), span)?;
+                    self.depth += 1;
+                    let expanded = self.expand_items(expansion, false);
+                    self.depth -= 1;
+                    expanded
+                } else {
+                    Ok(vec![Group(Delimiter::Parenthesis, self.expand_items(items, false)?, open, close)])
+                }
+            },
+            Group(delimiter, items, open, close) => Ok(vec![Group(delimiter, self.expand_items(items, false)?, open, close)]),
+            atom => Ok(vec![atom]),
+        }
+    }
+
+    /// Defines a macro from `(define-macro name (syntax-rules (literal ...) (pattern template) ...))`.
+    fn define(&mut self, items: Vec<Syntax>, close: Span) -> Result<(), CodyError> {
+        let mut items = items.into_iter().skip(1);
+        let name = match items.next() {
+            Some(Atom(Identifier(name), ..)) => name,
+            other => return Err(unexpected(other, close)),
+        };
+        let (rules, rules_close) = match items.next() {
+            Some(Group(Delimiter::Parenthesis, rules, _, rules_close)) => (rules, rules_close),
+            other => return Err(unexpected(other, close)),
+        };
+        if let Some(extra) = items.next() {
+            return Err(unexpected(Some(extra), close));
+        }
+
+        let mut rules = rules.into_iter();
+        match rules.next() {
+            Some(Atom(SyntaxRules, ..)) => (),
+            other => return Err(unexpected(other, rules_close)),
+        }
+        let literals: Vec<String> = match rules.next() {
+            Some(Group(Delimiter::Parenthesis, literals, ..)) => literals.into_iter()
+                .map(|literal| match literal {
+                    Atom(Identifier(name), ..) => Ok(name),
+                    other => Err(unexpected(Some(other), rules_close)),
+                })
+                .collect::<Result<_, _>>()?,
+            other => return Err(unexpected(other, rules_close)),
+        };
+        let rules: Vec<(Syntax, Vec<Syntax>)> = rules
+            .map(|rule| match rule {
+                Group(Delimiter::Parenthesis, rule, _, rule_close) => {
+                    let mut rule = rule.into_iter();
+                    // the first item of a pattern stands for the macro's name
+                    let pattern = match rule.next() {
+                        Some(Group(Delimiter::Parenthesis, patterns, open, close)) if !patterns.is_empty() => {
+                            check_pattern(&patterns[1..])?;
+                            Group(Delimiter::Parenthesis, patterns, open, close)
+                        },
+                        other => return Err(unexpected(other, rule_close)),
+                    };
+                    let template: Vec<Syntax> = rule.collect();
+                    match template.is_empty() {
+                        true => Err(unexpected_token(RightPar, rule_close)),
+                        false => Ok((pattern, template)),
+                    }
+                },
+                other => Err(unexpected(Some(other), rules_close)),
+            })
+            .collect::<Result<_, _>>()?;
+
+        self.macros.insert(name, Macro { literals, rules });
+        Ok(())
+    }
+
+    /// Expands a use of a macro with the first of its rules that matches it.
+    fn apply(&mut self, name: &str, form: Syntax, span: Span) -> Result<Vec<Syntax>, CodyError> {
+        let mark = self.marks + 1;
+        let definition = &self.macros[name];
+        let expansion = definition.rules.iter().find_map(|(pattern, template)| {
+            let (patterns, items) = match (pattern, &form) {
+                (Group(_, patterns, ..), Group(_, items, ..)) => (patterns, items),
+                _ => unreachable!(),
+            };
+            // the macro's name is not matched
+            let mut bindings: HashMap<String, Binding> = HashMap::new();
+            match_sequence(&patterns[1..], &items[1..], &definition.literals, &mut bindings)
+                .then(|| substitute_items(template, &bindings, mark, span))
+        });
+        self.marks = mark;
+        expansion.unwrap_or_else(|| {
+            Err(CodyError::new(ErrorCode::NoMatchingRule, format!("No rule of macro {} matches this use.", name), span))
+        })
+    }
+}
+
+/// The error for a malformed macro definition, where `syntax` is the unexpected item and `close`
+/// ends the grouping that was expected to end before it.
+fn unexpected(syntax: Option<Syntax>, close: Span) -> CodyError {
+    match syntax {
+        Some(Atom(token, span, _)) => unexpected_token(token, span),
+        Some(Group(Delimiter::Parenthesis, _, open, _)) => unexpected_token(LeftPar, open),
+        Some(Group(Delimiter::Bracket, _, open, _)) => unexpected_token(LeftBkt, open),
+        None => unexpected_token(RightPar, close),
+    }
+}
+
+/// Checks that every ellipsis in the items of a pattern follows a pattern, and that no grouping
+/// has two.
+fn check_pattern(patterns: &[Syntax]) -> Result<(), CodyError> {
+    let mut ellipses = patterns.iter().enumerate().filter(|(_, pattern)| matches!(pattern, Atom(Ellipsis, ..)));
+    if let Some((0, Atom(_, span, _))) = ellipses.next() {
+        return Err(CodyError::new(ErrorCode::InvalidMacro, "An ellipsis must follow the pattern it repeats.", *span));
+    }
+    if let Some((_, Atom(_, span, _))) = ellipses.next() {
+        return Err(CodyError::new(ErrorCode::InvalidMacro, "A grouping in a pattern can only have one ellipsis.", *span));
+    }
+    patterns.iter().try_for_each(|pattern| match pattern {
+        Group(_, patterns, ..) => check_pattern(patterns),
+        Atom(..) => Ok(()),
+    })
+}
+
+fn match_pattern(pattern: &Syntax, form: &Syntax, literals: &[String], bindings: &mut HashMap<String, Binding>) -> bool {
+    match (pattern, form) {
+        (Atom(Identifier(name), ..), _) if name == "_" => true,
+        (Atom(Identifier(name), ..), Atom(Identifier(form_name), ..)) if literals.contains(name) => name == form_name,
+        (Atom(Identifier(name), ..), _) if !literals.contains(name) => {
+            bindings.insert(name.clone(), Binding::One(form.clone()));
+            true
+        },
+        (Atom(token, ..), Atom(form_token, ..)) => token == form_token,
+        (Group(delimiter, patterns, ..), Group(form_delimiter, items, ..)) if delimiter == form_delimiter => {
+            match_sequence(patterns, items, literals, bindings)
+        },
+        _ => false,
+    }
+}
+
+/// Matches the items of a grouping, where the pattern followed by an ellipsis matches as many
+/// items as are left over by the patterns around it.
+fn match_sequence(patterns: &[Syntax], items: &[Syntax], literals: &[String], bindings: &mut HashMap<String, Binding>) -> bool {
+    let ellipsis = patterns.iter().position(|pattern| matches!(pattern, Atom(Ellipsis, ..)));
+    let Some(ellipsis) = ellipsis else {
+        return patterns.len() == items.len()
+            && patterns.iter().zip(items).all(|(pattern, item)| match_pattern(pattern, item, literals, bindings));
+    };
+
+    let (before, repeated, after) = (&patterns[..ellipsis - 1], &patterns[ellipsis - 1], &patterns[ellipsis + 1..]);
+    if items.len() < before.len() + after.len() {
+        return false;
+    }
+    let repeated_items = &items[before.len()..items.len() - after.len()];
+    let matched_before = before.iter().zip(items).all(|(pattern, item)| match_pattern(pattern, item, literals, bindings));
+    let matched_after = after.iter().zip(&items[items.len() - after.len()..])
+        .all(|(pattern, item)| match_pattern(pattern, item, literals, bindings));
+    if !matched_before || !matched_after {
+        return false;
+    }
+
+    let mut repetitions: Vec<HashMap<String, Binding>> = Vec::new();
+    for item in repeated_items {
+        let mut repetition: HashMap<String, Binding> = HashMap::new();
+        if !match_pattern(repeated, item, literals, &mut repetition) {
+            return false;
+        }
+        repetitions.push(repetition);
+    }
+    let mut variables: Vec<String> = Vec::new();
+    pattern_variables(repeated, literals, &mut variables);
+    for variable in variables {
+        let matches: Vec<Binding> = repetitions.iter_mut().map(|repetition| repetition.remove(&variable).unwrap()).collect();
+        bindings.insert(variable, Binding::Many(matches));
+    }
+    true
+}
+
+fn pattern_variables(pattern: &Syntax, literals: &[String], variables: &mut Vec<String>) {
+    match pattern {
+        Atom(Identifier(name), ..) if name != "_" && !literals.contains(name) => variables.push(name.clone()),
+        Atom(..) => (),
+        Group(_, patterns, ..) => patterns.iter().for_each(|pattern| pattern_variables(pattern, literals, variables)),
+    }
+}
+
+/// Fills in a template with the bindings of its pattern. The tokens of the template are marked
+/// as introduced by this expansion, and are placed at the macro's use.
+fn substitute(template: &Syntax, bindings: &HashMap<String, Binding>, mark: Mark, span: Span) -> Result<Syntax, CodyError> {
+    match template {
+        Atom(Identifier(name), template_span, _) => match bindings.get(name) {
+            Some(Binding::One(syntax)) => Ok(syntax.clone()),
+            Some(Binding::Many(_)) => Err(CodyError::new(
+                ErrorCode::InvalidMacro,
+                format!("Pattern variable {} must be followed by an ellipsis.", name),
+                *template_span
+            )),
+            None => Ok(Atom(Identifier(name.clone()), span, mark)),
+        },
+        Atom(token, ..) => Ok(Atom(token.clone(), span, mark)),
+        Group(delimiter, templates, ..) => Ok(Group(*delimiter, substitute_items(templates, bindings, mark, span)?, span, span)),
+    }
+}
+
+/// Fills in a sequence of templates, repeating the ones followed by an ellipsis. A template is
+/// repeated together with the quotes and splices before it, so that `@value ...` splices every
+/// value.
+fn substitute_items(templates: &[Syntax], bindings: &HashMap<String, Binding>, mark: Mark, span: Span) -> Result<Vec<Syntax>, CodyError> {
+    let mut items: Vec<Syntax> = Vec::new();
+    let mut templates = templates.iter().peekable();
+    while let Some(template) = templates.next() {
+        let mut unit: Vec<&Syntax> = vec![template];
+        while matches!(unit.last(), Some(Atom(Quote | Grave | At, ..))) {
+            match templates.next() {
+                Some(template) => unit.push(template),
+                None => break,
+            }
+        }
+        if !matches!(templates.peek(), Some(Atom(Ellipsis, ..))) {
+            for template in unit {
+                items.push(substitute(template, bindings, mark, span)?);
+            }
+            continue;
+        }
+        let Some(Atom(_, ellipsis_span, _)) = templates.next() else { unreachable!() };
+
+        // the template is repeated for every match of the repeated variables in it
+        let mut variables: Vec<String> = Vec::new();
+        unit.iter().for_each(|template| pattern_variables(template, &[], &mut variables));
+        let repeated: Vec<(&String, &Vec<Binding>)> = variables.iter()
+            .filter_map(|variable| match bindings.get(variable) {
+                Some(Binding::Many(matches)) => Some((variable, matches)),
+                _ => None,
+            })
+            .collect();
+        let repetitions = match repeated.first() {
+            Some((_, matches)) => matches.len(),
+            None => return Err(CodyError::new(ErrorCode::InvalidMacro, "An ellipsis must follow a repeated pattern variable.", *ellipsis_span)),
+        };
+        if repeated.iter().any(|(_, matches)| matches.len() != repetitions) {
+            return Err(CodyError::new(ErrorCode::InvalidMacro, "Pattern variables repeated together matched different numbers of items.", span));
+        }
+        for i in 0..repetitions {
+            let mut repetition: HashMap<String, Binding> = bindings.clone();
+            for (variable, matches) in repeated.iter() {
+                repetition.insert((*variable).clone(), matches[i].clone());
+            }
+            for template in unit.iter() {
+                items.push(substitute(template, &repetition, mark, span)?);
+            }
+        }
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn without_spans(tokens: Vec<(Token, Span)>) -> Vec<Token> {
+        tokens.into_iter().map(|(token, _)| token).collect()
+    }
+
+    /// The tokens of a program after its macros are expanded.
+    fn expanded(program: &str) -> Vec<Token> {
+        let tokens = lexer::lex(program).expect("Failed to lex the program.");
+        without_spans(expand(&tokens).expect("Failed to expand the program."))
+    }
+
+    /// The tokens of a program that has no macros.
+    fn written(program: &str) -> Vec<Token> {
+        without_spans(lexer::lex(program).expect("Failed to lex the program."))
+    }
+
+    /// The identifiers of the expansion of a program, in order.
+    fn identifiers(program: &str) -> Vec<String> {
+        expanded(program).into_iter()
+            .filter_map(|token| match token {
+                Identifier(name) => Some(name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ellipsis_repeats_the_matches_of_its_pattern() {
+        assert_eq!(expanded("(when ready a b c)"), written("(if ready (seq a b c ()) ())"));
+        assert_eq!(expanded("(when ready)"), written("(if ready (seq ()) ())"));
+    }
+
+    #[test]
+    fn nested_ellipses_take_apart_each_repetition() {
+        assert_eq!(expanded("(let ((a 1) (b 2)) a b)"), written("((fn (a b) (seq a b)) 1 2)"));
+    }
+
+    #[test]
+    fn literals_and_rules_are_tried_in_order() {
+        assert_eq!(expanded("(cond (x 1) (else 2))"), written("(if x (seq 1) (seq 2))"));
+        assert_eq!(expanded("(and)"), written("true"));
+    }
+
+    #[test]
+    fn bound_identifiers_of_a_template_do_not_capture_those_of_the_use() {
+        // or binds the value it tests as first, while the program uses its own first
+        let identifiers: Vec<String> = identifiers("(or false first)");
+        let renamed: String = identifiers[0].clone();
+        assert!(renamed.starts_with("first%"));
+        assert_eq!(identifiers, vec![renamed.clone(), renamed.clone(), renamed, "first".to_string()]);
+    }
+
+    #[test]
+    fn identifiers_of_the_use_keep_their_name_inside_the_template() {
+        let program = "
+            (define-macro swap
+                (syntax-rules ()
+                    ((_ pair) (let ((head (car pair)) (tail (cdr pair))) [tail . head]))))
+            (swap [head . 2])";
+        let identifiers: Vec<String> = identifiers(program);
+        // the head of the pair given is written twice in the template
+        assert_eq!(identifiers.iter().filter(|name| *name == "head").count(), 2);
+        assert!(identifiers.iter().any(|name| name.starts_with("head%")));
+        assert!(identifiers.iter().any(|name| name.starts_with("tail%")));
+        // free identifiers of the template refer to the definitions at its use
+        assert!(identifiers.iter().any(|name| name == "car"));
+    }
+
+    #[test]
+    fn free_identifiers_of_a_template_are_captured_at_the_use() {
+        let program = "
+            (define-macro first
+                (syntax-rules ()
+                    ((_ pair) (car pair))))
+            (let ((car cdr)) (first [1 . 2]))";
+        // the definition of the macro leaves (), and the car of the template is the one the let binds
+        assert_eq!(expanded(program), written("() ((fn (car) (seq (car [1 . 2]))) cdr)"));
+    }
+
+    #[test]
+    fn form_without_matching_rule_is_an_error() {
+        let tokens = lexer::lex("(let (a) a)").unwrap();
+        assert_eq!(expand(&tokens).unwrap_err().code, ErrorCode::NoMatchingRule);
+    }
+}
@@ -1,11 +1,34 @@
 mod ast_generator;
 mod lexer;
+mod macro_expander;
 
 pub mod node_types;
 pub mod token_types;
 
+use crate::error::{CodyError, ErrorCode};
+use crate::parser::token_types::Token;
+
 /// Parses a program string into an AST.
-pub fn parse(program: &str) -> node_types::ExpressionAST {
-    let tokens = lexer::lex(program);
+pub fn parse(program: &str) -> Result<node_types::ExpressionAST, CodyError> {
+    let tokens = lexer::lex(program)?;
+    let tokens = macro_expander::expand(&tokens)?;
     ast_generator::ast_generate(&tokens)
-}
\ No newline at end of file
+}
+
+/// Whether a program goes on past the end of the text given: it has brackets left open, or a
+/// string that is not terminated. The REPL reads more lines until its input is finished.
+pub fn is_unfinished(program: &str) -> bool {
+    match lexer::lex(program) {
+        Ok(tokens) => {
+            let depth: i64 = tokens.iter()
+                .map(|(token, _)| match token {
+                    Token::LeftPar | Token::LeftBkt => 1,
+                    Token::RightPar | Token::RightBkt => -1,
+                    _ => 0,
+                })
+                .sum();
+            depth > 0
+        },
+        Err(error) => error.code == ErrorCode::UnterminatedString,
+    }
+}
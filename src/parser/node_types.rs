@@ -1,38 +1,118 @@
 //! Node types for the parser.
-use crate::parser::token_types::AtomBinary;
+use std::fmt;
+
+use crate::error::Span;
+use crate::parser::token_types::{AtomBinary, BitType};
 
 #[derive(Clone, Debug)]
 pub enum ExpressionAST {
     // variables
-    VariableExpr(String),
+    VariableExpr(String, Span), // name and where it appears in the program
 
     // data
     IntegerExpr(i32),
+    BitsExpr(u64, BitType), // bit-precise integer, extended to 64 bits, and its type
+    FloatExpr(f64),
+    BoolExpr(bool),
+    CharExpr(char),
+    StringExpr(String),
     NoneExpr, 
+    SymbolExpr(String), // symbol data, produced by quoting identifiers
     PairExpr(Box<ExpressionAST>, Box<ExpressionAST>), // pair data
     FunctionExpr(Vec<ExpressionAST>, Box<ExpressionAST>), // function parameters and expression
-    //ContExpr(Box<ExpressionAST>),  // continuation expression
+    OperatorExpr(AtomBinary), // atomic binary operator used as a function value
+    ClosureExpr(Vec<ExpressionAST>, Vec<ExpressionAST>, Box<ExpressionAST>), // captured variables, parameters and expression, produced by closure conversion
+    ContExpr(Box<ExpressionAST>, Span),  // continuation expression, calling its function with the current continuation
 
     // definitions
     DefineExpr(Box<ExpressionAST>, Box<ExpressionAST>), // identifier and expression
 
     // calls
-    CallExpr(Box<ExpressionAST>, Vec<ExpressionAST>), // function and arguments
+    CallExpr(Box<ExpressionAST>, Vec<ExpressionAST>, Span), // function, arguments and the whole call
 
     // conditionals
-    IfExpr(Box<ExpressionAST>, Box<ExpressionAST>, Box<ExpressionAST>), // predicate, then, else
+    IfExpr(Box<ExpressionAST>, Box<ExpressionAST>, Box<ExpressionAST>, Span), // predicate, then, else
 
     // match case
-    MatchExpr(Box<ExpressionAST>, Vec<ExpressionAST>), // expression and match arms
+    MatchExpr(Box<ExpressionAST>, Vec<ExpressionAST>, Span), // expression and match arms
     MatchArmExpr(Vec<ExpressionAST>, Box<ExpressionAST>),  // patterns and expression
+    VariantExpr(Box<ExpressionAST>, Vec<ExpressionAST>), // variant and the names bound to its fields, as a pattern
 
     // sequence expressions
     SeqExpr(Vec<ExpressionAST>), // list of expressions, sequences evaluate to their last expression
 
     // atomic binary expressions
-    AtomBinExpr(AtomBinary, Box<ExpressionAST>, Box<ExpressionAST>), // left, right, operator
+    AtomBinExpr(AtomBinary, Box<ExpressionAST>, Box<ExpressionAST>, Span), // operator, left, right
 
     // external functions
-    // ie calling c library sin with ((extern sin) 1.0)
-    //ExternExpr(Box<ExpressionAST>), // name of the external function 
+    // ie declaring c library putchar with (extern putchar (i32) i32)
+    ExternExpr(Box<ExpressionAST>, Vec<ForeignType>, ForeignType), // name, parameter types and return type of the external function
+
+    // enums
+    // ie declaring shapes with (enum shape (circle float) (rect float float) (point))
+    EnumExpr(Box<ExpressionAST>, Vec<Variant>), // name and variants of the enum
+}
+
+/// A variant of an enum, holding fields of the given types.
+#[derive(Clone, Debug)]
+pub struct Variant {
+    pub name: String,
+    pub span: Span,
+    pub fields: Vec<FieldType>,
+}
+
+/// The type of a field of a variant, as written in its enum.
+#[derive(Clone, Debug)]
+pub enum FieldType {
+    Named(String, Span), // a type such as int, u8 or the name of an enum
+    List(Box<FieldType>), // (list type)
+}
+
+/// The C types external functions can take and return.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ForeignType {
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    Ptr,
+    Void, // only as a return type
+}
+
+impl ForeignType {
+    pub fn from_name(name: &str) -> Option<ForeignType> {
+        match name {
+            "i8" => Some(ForeignType::I8),
+            "i16" => Some(ForeignType::I16),
+            "i32" => Some(ForeignType::I32),
+            "i64" => Some(ForeignType::I64),
+            "f32" => Some(ForeignType::F32),
+            "f64" => Some(ForeignType::F64),
+            "ptr" => Some(ForeignType::Ptr),
+            "void" => Some(ForeignType::Void),
+            _ => None,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, ForeignType::F32 | ForeignType::F64)
+    }
+}
+
+impl fmt::Display for ForeignType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ForeignType::I8 => "i8",
+            ForeignType::I16 => "i16",
+            ForeignType::I32 => "i32",
+            ForeignType::I64 => "i64",
+            ForeignType::F32 => "f32",
+            ForeignType::F64 => "f64",
+            ForeignType::Ptr => "ptr",
+            ForeignType::Void => "void",
+        };
+        write!(f, "{}", name)
+    }
 }
\ No newline at end of file
@@ -1,21 +1,24 @@
 //! Token types for the programming language cody.
 
+use std::fmt;
+
 /// The different types of tokens that the lexer can produce.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Token {
 
     // data types
 
     // atomic data types
-    // Char(char),
+    Char(char),
     Integer(i32),
-    // Float(f32),
-    // Bool(Boolean),
+    Bits(u64, BitType), // value extended to 64 bits, and its type
+    Float(f64),
+    Bool(bool),
+    Str(String),
 
     // defined data types
     Function,
-    // Enum(String),
-    // BitType(String),
+    Enum,
     
     // syntax 
 
@@ -49,6 +52,9 @@ pub enum Token {
     // external functions
     Extern,
 
+    // macros
+    DefineMacro, SyntaxRules, Ellipsis,
+
     // atomic binary operators
     AtomicOp(AtomBinary),
 
@@ -56,7 +62,7 @@ pub enum Token {
 }
 
 /// The different types of atomic binary operators.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AtomBinary {
     Add,
     Sub,
@@ -74,4 +80,98 @@ pub enum AtomBinary {
     Lt,
     // Leq,
     // Geq,
+
+    // operators on floats, written with a trailing .
+    FAdd,
+    FSub,
+    FMul,
+    FDiv,
+    FEq,
+    FLt,
+}
+
+impl AtomBinary {
+    /// The operator on floats written as this operator followed by a `.`, if there is one.
+    pub fn on_floats(&self) -> Option<AtomBinary> {
+        match self {
+            AtomBinary::Add => Some(AtomBinary::FAdd),
+            AtomBinary::Sub => Some(AtomBinary::FSub),
+            AtomBinary::Mul => Some(AtomBinary::FMul),
+            AtomBinary::Div => Some(AtomBinary::FDiv),
+            AtomBinary::Eq => Some(AtomBinary::FEq),
+            AtomBinary::Lt => Some(AtomBinary::FLt),
+            _ => None,
+        }
+    }
+
+    /// The operator as it is written in a program.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            AtomBinary::Add => "+",
+            AtomBinary::Sub => "-",
+            AtomBinary::Mul => "*",
+            AtomBinary::Div => "/",
+            AtomBinary::And => "&",
+            AtomBinary::Or => "|",
+            AtomBinary::Not => "!",
+            AtomBinary::Eq => "=",
+            AtomBinary::Lt => "<",
+            AtomBinary::FAdd => "+.",
+            AtomBinary::FSub => "-.",
+            AtomBinary::FMul => "*.",
+            AtomBinary::FDiv => "/.",
+            AtomBinary::FEq => "=.",
+            AtomBinary::FLt => "<.",
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, AtomBinary::FAdd | AtomBinary::FSub | AtomBinary::FMul | AtomBinary::FDiv | AtomBinary::FEq | AtomBinary::FLt)
+    }
+}
+
+/// The type of a bit-precise integer, written `u` for unsigned or `i` for signed, followed by
+/// its width in bits, from 1 to 64: `u8`, `i64` or `u3`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BitType {
+    pub width: u32,
+    pub signed: bool,
+}
+
+impl BitType {
+    pub fn from_name(name: &str) -> Option<BitType> {
+        let (signed, digits) = match name.strip_prefix('i') {
+            Some(digits) => (true, digits),
+            None => (false, name.strip_prefix('u')?),
+        };
+        // the width is written without a sign or leading zeros
+        if digits.is_empty() || digits.starts_with('0') || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        match digits.parse::<u32>() {
+            Ok(width) if (1..=64).contains(&width) => Some(BitType { width, signed }),
+            _ => None,
+        }
+    }
+
+    /// The smallest value of the type.
+    pub fn min(&self) -> i128 {
+        if self.signed { -(1 << (self.width - 1)) } else { 0 }
+    }
+
+    /// The largest value of the type.
+    pub fn max(&self) -> i128 {
+        if self.signed { (1 << (self.width - 1)) - 1 } else { (1 << self.width) - 1 }
+    }
+
+    /// The number standing for the type at run time.
+    pub fn code(&self) -> u64 {
+        (self.width as u64) << 1 | self.signed as u64
+    }
+}
+
+impl fmt::Display for BitType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", if self.signed { "i" } else { "u" }, self.width)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,104 @@
+//! The interactive REPL, started with `cody repl`.
+//! Reads one form at a time, waiting for more lines while it has brackets left open, then checks
+//! its type, runs it and prints its value. The definitions of a form stay in scope for the forms
+//! after it. Commands inspect a form instead of running it:
+//!
+//! - `:type form` prints the type of the form,
+//! - `:ast form` prints its syntax tree,
+//! - `:ir form` prints the LLVM IR it compiles to,
+//! - `:quit` ends the session, as does the end of the input.
+
+use std::io::{self, BufRead, Write};
+
+use inkwell::context::Context;
+
+use crate::checker::Inference;
+use crate::compiler::optimize::Optimization;
+use crate::compiler::session::{self, Session};
+use crate::error::CodyError;
+use crate::parser::{is_unfinished, parse};
+
+/// The name errors in the forms of the REPL are reported under.
+const SOURCE_NAME: &str = "<repl>";
+
+/// Runs the REPL until the end of its input, optimising each form at the given level. Forms are
+/// not type checked if `dynamic`.
+pub fn repl(dynamic: bool, optimization: Optimization) {
+    let context = Context::create();
+    let mut session = Session::new(&context, optimization);
+    let mut inference = Inference::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    while let Some(input) = read_form(&mut lines) {
+        let (command, source) = match input.trim_start().strip_prefix(':') {
+            Some(command) => {
+                let end = command.find(char::is_whitespace).unwrap_or(command.len());
+                (Some(&command[..end]), &command[end..])
+            },
+            None => (None, input.as_str()),
+        };
+        let result = match command {
+            None => evaluate(source, &mut session, &mut inference, dynamic),
+            Some("type") => parse(source)
+                .and_then(|ast| inference.clone().infer_program(&ast))
+                .map(|form_type| println!("{}", form_type)),
+            Some("ast") => parse(source).map(|ast| println!("{:#?}", ast)),
+            Some("ir") => parse(source)
+                .and_then(|ast| session.ir(ast))
+                .map(|ir| print!("{}", ir)),
+            Some("quit") => break,
+            Some(command) => {
+                eprintln!("Unknown command :{}, the commands are :type, :ast, :ir and :quit.", command);
+                Ok(())
+            },
+        };
+        if let Err(error) = result {
+            eprint!("{}", error.render(source, SOURCE_NAME));
+        }
+    }
+}
+
+/// Reads the lines of the next form, until it has no brackets left open. Returns `None` at the
+/// end of the input.
+fn read_form(lines: &mut impl Iterator<Item = io::Result<String>>) -> Option<String> {
+    let mut input = String::new();
+    loop {
+        print!("{}", if input.trim().is_empty() { "cody> " } else { "  ... " });
+        io::stdout().flush().expect("Failed to write prompt.");
+        match lines.next() {
+            Some(Ok(line)) => {
+                input.push_str(&line);
+                input.push('\n');
+            },
+            // an unfinished form at the end of the input is parsed as it is, to report it
+            _ if input.trim().is_empty() => {
+                println!();
+                return None;
+            },
+            _ => return Some(input),
+        }
+        if !input.trim().is_empty() && !is_unfinished(&input) {
+            return Some(input);
+        }
+    }
+}
+
+/// Runs a form, printing its value and its type. The definitions of the form are only added to
+/// those the checker knows once the session has compiled it.
+fn evaluate(source: &str, session: &mut Session, inference: &mut Inference, dynamic: bool) -> Result<(), CodyError> {
+    let ast = parse(source)?;
+    let mut checked: Inference = inference.clone();
+    let form_type = if dynamic { None } else { Some(checked.infer_program(&ast)?) };
+    let result: Option<u64> = session.run(ast)?;
+    *inference = checked;
+    // a form that ends with an error at run time has already reported it
+    if let Some(value) = result {
+        session::write(value);
+        match form_type {
+            Some(form_type) => println!(" : {}", form_type),
+            None => println!(),
+        }
+    }
+    Ok(())
+}
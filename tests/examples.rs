@@ -0,0 +1,62 @@
+//! Compiles example programs into executables and runs them, some with the memory they may use
+//! capped, checking their exit codes.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+/// Compiles an example into an executable, returning its path.
+fn compile(example: &str) -> PathBuf {
+    let source: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples").join(format!("{}.cdy", example));
+    let executable: PathBuf = env::temp_dir().join(format!("cody-test-{}-{}", process::id(), example));
+    let status = Command::new(env!("CARGO_BIN_EXE_cody"))
+        .arg("-i").arg(&source)
+        .arg("-o").arg(&executable)
+        .args(["--emit", "exe"])
+        .status()
+        .expect("Failed to run the compiler.");
+    assert!(status.success(), "Failed to compile {}.", example);
+    executable
+}
+
+/// Runs an executable, returning its exit code.
+fn run(executable: &Path) -> i32 {
+    let status = Command::new(executable).status().expect("Failed to run the program.");
+    let _ = fs::remove_file(executable);
+    status.code().unwrap_or_else(|| panic!("{} was killed by a signal.", executable.display()))
+}
+
+/// Runs an executable under the limits set by `ulimit` with the given options, returning its
+/// exit code.
+fn run_limited(executable: &Path, limits: &str) -> i32 {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("ulimit {} && exec \"$0\"", limits))
+        .arg(executable)
+        .status()
+        .expect("Failed to run the program.");
+    let _ = fs::remove_file(executable);
+    status.code().unwrap_or_else(|| panic!("{} was killed by a signal.", executable.display()))
+}
+
+/// Without the collector, the ten million pairs the program builds need hundreds of megabytes.
+#[test]
+fn collector_runs_allocation_heavy_program_in_capped_memory() {
+    let executable: PathBuf = compile("gc_stress");
+    assert_eq!(run_limited(&executable, "-v 131072"), 100);
+}
+
+/// Without tail calls, the ten million calls of the loop overflow a small stack.
+#[test]
+fn tail_calls_run_loop_in_capped_stack() {
+    let executable: PathBuf = compile("loop");
+    assert_eq!(run_limited(&executable, "-s 256"), 128);
+}
+
+/// Definitions named like the functions of libc must not take their place when linked.
+#[test]
+fn definitions_do_not_clash_with_c_functions() {
+    let executable: PathBuf = compile("names");
+    assert_eq!(run(&executable), 42);
+}